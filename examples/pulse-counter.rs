@@ -0,0 +1,72 @@
+//! Count rising edges on the TIM2 ETR pin (PA0) and report every 1000 pulses
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+use stm32f1xx_hal::{
+    afio::tim2::Etr,
+    pac::{self, interrupt},
+    prelude::*,
+    timer::{
+        pulse_counter::{EtrConfig, EtrPrescaler, PulseCounter},
+        Event, Timer,
+    },
+};
+
+type Counter = PulseCounter<pac::TIM2, Etr>;
+
+static COUNTER: Mutex<RefCell<Option<Counter>>> = Mutex::new(RefCell::new(None));
+static THRESHOLD: u32 = 1000;
+
+#[interrupt]
+fn TIM2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(counter) = COUNTER.borrow(cs).borrow_mut().as_mut() {
+            if counter.on_interrupt() {
+                // Arm the next threshold
+                let mut next = counter.count() / THRESHOLD * THRESHOLD + THRESHOLD;
+                // Skip the thresholds already passed while handling the interrupt
+                while counter.listen_threshold(next).is_err() {
+                    next += THRESHOLD;
+                }
+            }
+        }
+    });
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let gpioa = dp.GPIOA.split();
+
+    let mut counter = Timer::new(dp.TIM2, &clocks).pulse_counter_etr(
+        gpioa.pa0,
+        EtrConfig {
+            prescaler: EtrPrescaler::Div1,
+            filter: 3,
+            ..Default::default()
+        },
+    );
+    counter.listen(Event::Update);
+    counter.listen_threshold(THRESHOLD).unwrap();
+
+    cortex_m::interrupt::free(|cs| COUNTER.borrow(cs).replace(Some(counter)));
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(pac::Interrupt::TIM2);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
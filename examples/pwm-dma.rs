@@ -0,0 +1,82 @@
+//! Drive a strip of WS2812 LEDs from TIM2 channel 1 (PA0) with DMA
+//!
+//! Each bit is a 800 kHz PWM period: a `1` is a long pulse, a `0` a short one. The duty cycle of
+//! every period is loaded from a buffer by the TIM2 update DMA request (DMA1 channel 2).
+
+#![deny(unsafe_code)]
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use cortex_m::singleton;
+use cortex_m_rt::entry;
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    timer::{Tim2NoRemap, Timer},
+};
+
+const LEDS: usize = 8;
+// 24 bits per LED followed by a low level for the reset code
+const BUFFER_LEN: usize = LEDS * 24 + 40;
+
+fn encode(colors: &[[u8; 3]; LEDS], buffer: &mut [u16; BUFFER_LEN], one: u16, zero: u16) {
+    let mut bits = colors
+        .iter()
+        .flat_map(|&[r, g, b]| [g, r, b])
+        .flat_map(|byte| (0..8).rev().map(move |i| byte & (1 << i) != 0));
+    for duty in buffer.iter_mut() {
+        *duty = match bits.next() {
+            Some(true) => one,
+            Some(false) => zero,
+            None => 0,
+        };
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let p = pac::Peripherals::take().unwrap();
+
+    let mut flash = p.FLASH.constrain();
+    let rcc = p.RCC.constrain();
+
+    let clocks = rcc.cfgr.sysclk(72.MHz()).freeze(&mut flash.acr);
+
+    let mut afio = p.AFIO.constrain();
+    let mut gpioa = p.GPIOA.split();
+    let dma1 = p.DMA1.split();
+
+    let pin = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+    let mut c1 = Timer::new(p.TIM2, &clocks)
+        .pwm_hz::<Tim2NoRemap, _, _>(pin, &mut afio.mapr, 800.kHz())
+        .split();
+    c1.set_duty(0);
+    c1.enable();
+
+    // T1H is about 0.8 us and T0H about 0.4 us out of 1.25 us
+    let max = c1.get_max_duty();
+    let (one, zero) = (max * 2 / 3, max / 3);
+
+    let buffer = singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap();
+    let mut colors = [[0u8; 3]; LEDS];
+    let mut pwm_dma = c1.with_dma(dma1.2);
+    let mut delay = p.TIM3.delay_ms(&clocks);
+
+    let mut step = 0u8;
+    let mut buffer = buffer;
+    loop {
+        for (i, led) in colors.iter_mut().enumerate() {
+            *led = [step.wrapping_add(i as u8 * 32), 0, 32];
+        }
+        encode(&colors, buffer, one, zero);
+
+        let (b, channel) = pwm_dma.write(buffer).wait();
+        buffer = b;
+        pwm_dma = channel;
+
+        step = step.wrapping_add(4);
+        delay.delay(20.millis());
+    }
+}
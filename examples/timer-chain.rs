@@ -0,0 +1,44 @@
+//! Chain TIM2 and TIM3 to get a 32-bit microsecond counter
+//!
+//! TIM2 counts microseconds and clocks TIM3 on each overflow through the internal trigger
+//! connection, so the counter wraps after about 71 minutes instead of 65 ms.
+
+#![deny(unsafe_code)]
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use cortex_m_semihosting::hprintln;
+
+use cortex_m_rt::entry;
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    timer::{ChainedCounterUs, FTimerUs, Timer},
+};
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let master = FTimerUs::new(dp.TIM2, &clocks);
+    let slave = Timer::new(dp.TIM3, &clocks);
+    let counter = ChainedCounterUs::new(master, slave);
+
+    let mut delay = cp.SYST.delay(&clocks);
+
+    loop {
+        let before = counter.now();
+        delay.delay_ms(1_000_u16);
+        let after = counter.now();
+
+        hprintln!("{} us", (after - before).ticks());
+    }
+}
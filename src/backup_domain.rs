@@ -10,11 +10,82 @@
   Write access to the backup domain is enabled in RCC using the `rcc::Rcc::BKP::constrain()`
   function.
 
-  Only the RTC functionality is currently implemented.
+  Typed values can be stored across several data registers with a CRC guard, see
+  [`BackupDomain::store`].
+
+  A level on the TAMPER pin (PC13) can reset all the data registers, see
+  [`BackupDomain::enable_tamper`].
 */
 
+use crate::crc::Crc;
 use crate::pac::BKP;
 
+/// Number of 16-bit data registers
+#[cfg(not(any(feature = "high", feature = "connectivity")))]
+pub const DATA_REGISTERS: usize = 10;
+/// Number of 16-bit data registers
+#[cfg(any(feature = "high", feature = "connectivity"))]
+pub const DATA_REGISTERS: usize = 42;
+
+/// Value that can be stored in the data registers with [`BackupDomain::store`]
+pub trait BackupData: Sized {
+    /// Size of the serialized value in bytes
+    const SIZE: usize;
+    /// Serializes the value into `buf`, which is `SIZE` bytes long
+    fn to_bytes(&self, buf: &mut [u8]);
+    /// Deserializes a value from `buf`, which is `SIZE` bytes long
+    fn from_bytes(buf: &[u8]) -> Self;
+}
+
+macro_rules! backup_data {
+    ($($T:ty,)+) => {
+        $(
+            impl BackupData for $T {
+                const SIZE: usize = core::mem::size_of::<$T>();
+                fn to_bytes(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+                fn from_bytes(buf: &[u8]) -> Self {
+                    let mut bytes = [0; core::mem::size_of::<$T>()];
+                    bytes.copy_from_slice(buf);
+                    <$T>::from_le_bytes(bytes)
+                }
+            }
+        )+
+    };
+}
+
+backup_data!(u8, u16, u32, u64, i8, i16, i32, i64,);
+
+impl BackupData for bool {
+    const SIZE: usize = 1;
+    fn to_bytes(&self, buf: &mut [u8]) {
+        buf[0] = *self as u8;
+    }
+    fn from_bytes(buf: &[u8]) -> Self {
+        buf[0] != 0
+    }
+}
+
+impl<const N: usize> BackupData for [u8; N] {
+    const SIZE: usize = N;
+    fn to_bytes(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self);
+    }
+    fn from_bytes(buf: &[u8]) -> Self {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(buf);
+        bytes
+    }
+}
+
+/// Level of the TAMPER pin that triggers a tamper event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TamperLevel {
+    High,
+    Low,
+}
+
 /**
   The existence of this struct indicates that writing to the the backup
   domain has been enabled. It is acquired by calling `constrain` on `rcc::Rcc::BKP`
@@ -71,4 +142,131 @@ impl BackupDomain {
     pub fn write_data_register_high(&self, register: usize, data: u16) {
         write_drx!(self, bkp_dr, register, data);
     }
+
+    /// Enables tamper detection on the TAMPER pin (PC13)
+    ///
+    /// When the pin goes to `level`, all the data registers are reset and the tamper event flag
+    /// is set. PC13 is taken over whatever its GPIO configuration, and cannot be used for the
+    /// RTC output at the same time.
+    pub fn enable_tamper(&mut self, level: TamperLevel) {
+        // The active level must be selected before enabling, or a spurious event may occur
+        self._regs.cr().modify(|_, w| w.tpe().general());
+        self._regs.cr().modify(|_, w| match level {
+            TamperLevel::High => w.tpal().high(),
+            TamperLevel::Low => w.tpal().low(),
+        });
+        self._regs.cr().modify(|_, w| w.tpe().alternate());
+    }
+
+    /// Disables tamper detection, PC13 is available as GPIO again
+    pub fn disable_tamper(&mut self) {
+        self._regs.cr().modify(|_, w| w.tpe().general());
+    }
+
+    /// Enables the `TAMPER` interrupt on tamper events
+    pub fn listen_tamper(&mut self) {
+        self._regs.csr().modify(|_, w| w.tpie().enabled());
+    }
+
+    /// Disables the `TAMPER` interrupt
+    pub fn unlisten_tamper(&mut self) {
+        self._regs.csr().modify(|_, w| w.tpie().disabled());
+    }
+
+    /// Returns `true` if a tamper event has occurred, i.e. the data registers have been wiped
+    ///
+    /// The flag is kept across resets and standby mode. The data registers cannot be written
+    /// until it is cleared with [`clear_tamper_event`](Self::clear_tamper_event).
+    pub fn is_tampered(&self) -> bool {
+        self._regs.csr().read().tef().bit_is_set()
+    }
+
+    /// Returns `true` if the tamper interrupt is pending
+    pub fn is_tamper_interrupt(&self) -> bool {
+        self._regs.csr().read().tif().bit_is_set()
+    }
+
+    /// Clears the tamper event and interrupt flags
+    ///
+    /// Tamper detection should be disabled first if the pin is still at its active level,
+    /// otherwise a new event is detected.
+    pub fn clear_tamper_event(&mut self) {
+        self._regs
+            .csr()
+            .modify(|_, w| w.cte().reset().cti().clear());
+    }
+
+    /// Returns the number of data registers used by [`store`](Self::store) for a `T`
+    pub fn registers_for<T: BackupData>() -> usize {
+        // Data rounded up to 16 bits, followed by the 32-bit CRC
+        (T::SIZE + 1) / 2 + 2
+    }
+
+    /// Stores `value` with a CRC from data register `first` (zero based index, 0 is DR1)
+    ///
+    /// Registers `first` to `first + registers_for::<T>() - 1` are used, across DR1 to DR10 and
+    /// DR11 to DR42 on high-density and connectivity line devices. Panics if they do not exist.
+    pub fn store<T: BackupData>(&mut self, crc: &mut Crc, first: usize, value: &T) {
+        let mut buf = [0; DATA_REGISTERS * 2];
+        let len = Self::registers_for::<T>();
+        assert!(first + len <= DATA_REGISTERS);
+        value.to_bytes(&mut buf[..T::SIZE]);
+
+        let data = &buf[..(len - 2) * 2];
+        for (i, half) in data.chunks(2).enumerate() {
+            self.write_data_register(first + i, u16::from_le_bytes([half[0], half[1]]));
+        }
+        let checksum = checksum(crc, data);
+        self.write_data_register(first + len - 2, checksum as u16);
+        self.write_data_register(first + len - 1, (checksum >> 16) as u16);
+    }
+
+    /// Loads a value stored by [`store`](Self::store) from data register `first`
+    ///
+    /// Returns `None` if the CRC does not match, e.g. the registers have never been written or
+    /// have been reset by a tamper event or a loss of both VDD and VBAT.
+    pub fn load<T: BackupData>(&self, crc: &mut Crc, first: usize) -> Option<T> {
+        let mut buf = [0; DATA_REGISTERS * 2];
+        let len = Self::registers_for::<T>();
+        assert!(first + len <= DATA_REGISTERS);
+
+        let data = &mut buf[..(len - 2) * 2];
+        for (i, half) in data.chunks_mut(2).enumerate() {
+            half.copy_from_slice(&self.read_data_register(first + i).to_le_bytes());
+        }
+        let stored = self.read_data_register(first + len - 2) as u32
+            | (self.read_data_register(first + len - 1) as u32) << 16;
+        if checksum(crc, data) != stored {
+            return None;
+        }
+        Some(T::from_bytes(&data[..T::SIZE]))
+    }
+
+    fn read_data_register(&self, register: usize) -> u16 {
+        #[cfg(any(feature = "high", feature = "connectivity"))]
+        if register >= 10 {
+            return self.read_data_register_high(register - 10);
+        }
+        self.read_data_register_low(register)
+    }
+
+    fn write_data_register(&self, register: usize, data: u16) {
+        #[cfg(any(feature = "high", feature = "connectivity"))]
+        if register >= 10 {
+            return self.write_data_register_high(register - 10, data);
+        }
+        self.write_data_register_low(register, data)
+    }
+}
+
+/// CRC-32 (Ethernet polynomial) of the data length and the data, padded to 32-bit words
+fn checksum(crc: &mut Crc, data: &[u8]) -> u32 {
+    crc.reset();
+    crc.write(data.len() as u32);
+    for word in data.chunks(4) {
+        let mut bytes = [0; 4];
+        bytes[..word.len()].copy_from_slice(word);
+        crc.write(u32::from_le_bytes(bytes));
+    }
+    crc.read()
 }
@@ -5,7 +5,7 @@ use crate::pac::{flash, FLASH};
 pub const FLASH_START: u32 = 0x0800_0000;
 pub const FLASH_END: u32 = 0x080F_FFFF;
 
-const _RDPRT_KEY: u16 = 0x00A5;
+const RDPRT_KEY: u8 = 0xA5;
 const KEY1: u32 = 0x45670123;
 const KEY2: u32 = 0xCDEF89AB;
 
@@ -57,48 +57,73 @@ impl FlashSize {
     }
 }
 
-pub struct FlashWriter<'a> {
-    flash: &'a mut Parts,
-    sector_sz: SectorSize,
-    flash_sz: FlashSize,
-    verify: bool,
+/// Size of the first bank on XL-density devices
+#[cfg(feature = "xl")]
+pub const BANK1_SIZE: u32 = 512 * SZ_1K as u32;
+
+/// Flash bank, with its own KEYR, SR, CR and AR registers
+///
+/// XL-density devices (768 KB and 1 MB) have a second bank. The CPU keeps running from one
+/// bank while the other one is erased or programmed, and stalls when it accesses the busy
+/// bank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bank {
+    /// First 512 KB on XL-density devices, the whole flash otherwise
+    Bank1,
+    /// Flash above [`BANK1_SIZE`]
+    #[cfg(feature = "xl")]
+    Bank2,
 }
-impl FlashWriter<'_> {
-    fn unlock(&mut self) -> Result<()> {
-        // Wait for any ongoing operations
-        while self.flash.sr.sr().read().bsy().bit_is_set() {}
 
-        // NOTE(unsafe) write Keys to the key register. This is safe because the
-        // only side effect of these writes is to unlock the flash control
-        // register, which is the intent of this function. Do not rearrange the
-        // order of these writes or the control register will be permanently
-        // locked out until reset.
-        unsafe {
-            self.flash.keyr.keyr().write(|w| w.key().bits(KEY1));
-        }
-        unsafe {
-            self.flash.keyr.keyr().write(|w| w.key().bits(KEY2));
+impl Bank {
+    /// Returns the bank containing `FLASH_START + offset`
+    pub const fn of(offset: u32) -> Self {
+        #[cfg(feature = "xl")]
+        if offset >= BANK1_SIZE {
+            return Self::Bank2;
         }
+        let _ = offset;
+        Self::Bank1
+    }
 
-        // Verify success
-        match self.flash.cr.cr().read().lock().bit_is_clear() {
-            true => Ok(()),
-            false => Err(Error::UnlockError),
+    /// Offsets of the first and after the last byte of the bank
+    const fn range(self, flash_sz: FlashSize) -> (u32, u32) {
+        match self {
+            #[cfg(feature = "xl")]
+            Self::Bank1 if flash_sz.kbytes() > BANK1_SIZE => (0, BANK1_SIZE),
+            Self::Bank1 => (0, flash_sz.kbytes()),
+            #[cfg(feature = "xl")]
+            Self::Bank2 => (BANK1_SIZE, flash_sz.kbytes()),
         }
     }
 
-    fn lock(&mut self) -> Result<()> {
-        //Wait for ongoing flash operations
-        while self.flash.sr.sr().read().bsy().bit_is_set() {}
+    /// Registers of the bank
+    fn regs(self) -> *const flash::RegisterBlock {
+        match self {
+            Self::Bank1 => FLASH::ptr(),
+            // KEYR2, SR2, CR2 and AR2 are 0x40 above KEYR, SR, CR and AR, with the same layout
+            #[cfg(feature = "xl")]
+            Self::Bank2 => (FLASH::ptr() as usize + 0x40) as *const flash::RegisterBlock,
+        }
+    }
+}
 
-        // Set lock bit
-        self.flash.cr.cr().modify(|_, w| w.lock().set_bit());
+/// Erases and programs the flash
+///
+/// On XL-density devices, the operations are dispatched to the bank of each address.
+pub struct FlashWriter<'a> {
+    flash: &'a mut Parts,
+    sector_sz: SectorSize,
+    flash_sz: FlashSize,
+    verify: bool,
+}
+impl FlashWriter<'_> {
+    fn unlock(&mut self, bank: Bank) -> Result<()> {
+        self.flash.unlock(bank)
+    }
 
-        // Verify success
-        match self.flash.cr.cr().read().lock().bit_is_set() {
-            true => Ok(()),
-            false => Err(Error::LockError),
-        }
+    fn lock(&mut self, bank: Bank) -> Result<()> {
+        self.flash.lock(bank)
     }
 
     fn valid_address(&self, offset: u32) -> Result<()> {
@@ -124,12 +149,13 @@ impl FlashWriter<'_> {
     /// Erase sector which contains `start_offset`
     pub fn page_erase(&mut self, start_offset: u32) -> Result<()> {
         self.valid_address(start_offset)?;
+        let bank = Bank::of(start_offset);
 
         // Unlock Flash
-        self.unlock()?;
+        self.unlock(bank)?;
 
         // Set Page Erase
-        self.flash.cr.cr().modify(|_, w| w.per().set_bit());
+        self.flash.cr.cr_bank(bank).modify(|_, w| w.per().set_bit());
 
         // Write address bits
         // NOTE(unsafe) This sets the page address in the Address Register.
@@ -139,12 +165,15 @@ impl FlashWriter<'_> {
         unsafe {
             self.flash
                 .ar
-                .ar()
+                .ar_bank(bank)
                 .write(|w| w.far().bits(FLASH_START + start_offset));
         }
 
         // Start Operation
-        self.flash.cr.cr().modify(|_, w| w.strt().set_bit());
+        self.flash
+            .cr
+            .cr_bank(bank)
+            .modify(|_, w| w.strt().set_bit());
 
         // Wait for at least one clock cycle before reading the
         // BSY bit, because there is a one-cycle delay between
@@ -154,20 +183,26 @@ impl FlashWriter<'_> {
         cortex_m::asm::nop();
 
         // Wait for operation to finish
-        while self.flash.sr.sr().read().bsy().bit_is_set() {}
+        while self.flash.sr.sr_bank(bank).read().bsy().bit_is_set() {}
 
         // Check for errors
-        let sr = self.flash.sr.sr().read();
+        let sr = self.flash.sr.sr_bank(bank).read();
 
         // Remove Page Erase Operation bit
-        self.flash.cr.cr().modify(|_, w| w.per().clear_bit());
+        self.flash
+            .cr
+            .cr_bank(bank)
+            .modify(|_, w| w.per().clear_bit());
 
         // Re-lock flash
-        self.lock()?;
+        self.lock(bank)?;
 
         if sr.wrprterr().bit_is_set() {
             // reset by writing 1
-            self.flash.sr.sr().modify(|_, w| w.wrprterr().bit(true));
+            self.flash
+                .sr
+                .sr_bank(bank)
+                .modify(|_, w| w.wrprterr().bit(true));
             Err(Error::EraseError)
         } else {
             if self.verify {
@@ -206,6 +241,53 @@ impl FlashWriter<'_> {
         Ok(())
     }
 
+    /// Erase all the pages of `bank`
+    ///
+    /// The code must not run from the erased bank, e.g. it can be copied to SRAM first.
+    pub fn mass_erase(&mut self, bank: Bank) -> Result<()> {
+        self.unlock(bank)?;
+
+        self.flash.cr.cr_bank(bank).modify(|_, w| w.mer().set_bit());
+        self.flash
+            .cr
+            .cr_bank(bank)
+            .modify(|_, w| w.strt().set_bit());
+
+        // One cycle delay before BSY is set, see `page_erase`
+        cortex_m::asm::nop();
+        while self.flash.sr.sr_bank(bank).read().bsy().bit_is_set() {}
+
+        let sr = self.flash.sr.sr_bank(bank).read();
+        self.flash
+            .cr
+            .cr_bank(bank)
+            .modify(|_, w| w.mer().clear_bit());
+        self.lock(bank)?;
+
+        if sr.wrprterr().bit_is_set() {
+            // reset by writing 1
+            self.flash
+                .sr
+                .sr_bank(bank)
+                .modify(|_, w| w.wrprterr().bit(true));
+            return Err(Error::EraseError);
+        }
+
+        if self.verify {
+            let (start, end) = bank.range(self.flash_sz);
+            for idx in (start..end).step_by(2) {
+                let address = (FLASH_START + idx) as *const u16;
+                // NOTE(unsafe) read with no side effects within FLASH area
+                let verify: u16 = unsafe { core::ptr::read_volatile(address) };
+                if verify != 0xFFFF {
+                    return Err(Error::VerifyError);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Retrieve a slice of data from `FLASH_START + offset`
     pub fn read(&self, offset: u32, length: usize) -> Result<&[u8]> {
         self.valid_address(offset)?;
@@ -230,8 +312,20 @@ impl FlashWriter<'_> {
     pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<()> {
         self.valid_length(offset, data.len())?;
 
+        // Each bank is programmed with its own registers
+        #[cfg(feature = "xl")]
+        if offset < BANK1_SIZE && offset + data.len() as u32 > BANK1_SIZE {
+            let (data1, data2) = data.split_at((BANK1_SIZE - offset) as usize);
+            self.write_bank(Bank::Bank1, offset, data1)?;
+            return self.write_bank(Bank::Bank2, BANK1_SIZE, data2);
+        }
+
+        self.write_bank(Bank::of(offset), offset, data)
+    }
+
+    fn write_bank(&mut self, bank: Bank, offset: u32, data: &[u8]) -> Result<()> {
         // Unlock Flash
-        self.unlock()?;
+        self.unlock(bank)?;
 
         for idx in (0..data.len()).step_by(2) {
             self.valid_address(offset + idx as u32)?;
@@ -239,9 +333,9 @@ impl FlashWriter<'_> {
             let write_address = (FLASH_START + offset + idx as u32) as *mut u16;
 
             // Set Page Programming to 1
-            self.flash.cr.cr().modify(|_, w| w.pg().set_bit());
+            self.flash.cr.cr_bank(bank).modify(|_, w| w.pg().set_bit());
 
-            while self.flash.sr.sr().read().bsy().bit_is_set() {}
+            while self.flash.sr.sr_bank(bank).read().bsy().bit_is_set() {}
 
             // Flash is written 16 bits at a time, so combine two bytes to get a
             // half-word
@@ -251,37 +345,46 @@ impl FlashWriter<'_> {
             unsafe { core::ptr::write_volatile(write_address, hword) };
 
             // Wait for write
-            while self.flash.sr.sr().read().bsy().bit_is_set() {}
+            while self.flash.sr.sr_bank(bank).read().bsy().bit_is_set() {}
 
             // Set Page Programming to 0
-            self.flash.cr.cr().modify(|_, w| w.pg().clear_bit());
+            self.flash
+                .cr
+                .cr_bank(bank)
+                .modify(|_, w| w.pg().clear_bit());
 
             // Check for errors
-            if self.flash.sr.sr().read().pgerr().bit_is_set() {
+            if self.flash.sr.sr_bank(bank).read().pgerr().bit_is_set() {
                 // reset by writing 1
-                self.flash.sr.sr().modify(|_, w| w.pgerr().bit(true));
+                self.flash
+                    .sr
+                    .sr_bank(bank)
+                    .modify(|_, w| w.pgerr().bit(true));
 
-                self.lock()?;
+                self.lock(bank)?;
                 return Err(Error::ProgrammingError);
-            } else if self.flash.sr.sr().read().wrprterr().bit_is_set() {
+            } else if self.flash.sr.sr_bank(bank).read().wrprterr().bit_is_set() {
                 // reset by writing 1
-                self.flash.sr.sr().modify(|_, w| w.wrprterr().bit(true));
+                self.flash
+                    .sr
+                    .sr_bank(bank)
+                    .modify(|_, w| w.wrprterr().bit(true));
 
-                self.lock()?;
+                self.lock(bank)?;
                 return Err(Error::WriteError);
             } else if self.verify {
                 // Verify written WORD
                 // NOTE(unsafe) read with no side effects within FLASH area
                 let verify: u16 = unsafe { core::ptr::read_volatile(write_address) };
                 if verify != hword {
-                    self.lock()?;
+                    self.lock(bank)?;
                     return Err(Error::VerifyError);
                 }
             }
         }
 
         // Lock Flash and report success
-        self.lock()?;
+        self.lock(bank)?;
         Ok(())
     }
 
@@ -313,10 +416,10 @@ impl FlashExt for FLASH {
             ar: AR,
             cr: CR,
             keyr: KEYR,
-            _obr: OBR,
-            _optkeyr: OPTKEYR,
+            obr: OBR,
+            optkeyr: OPTKEYR,
             sr: SR,
-            _wrpr: WRPR,
+            wrpr: WRPR,
         }
     }
 }
@@ -336,18 +439,55 @@ pub struct Parts {
     pub(crate) keyr: KEYR,
 
     /// Opaque OBR register
-    pub(crate) _obr: OBR,
+    pub(crate) obr: OBR,
 
     /// Opaque OPTKEYR register
-    pub(crate) _optkeyr: OPTKEYR,
+    pub(crate) optkeyr: OPTKEYR,
 
     /// Opaque SR register
     pub(crate) sr: SR,
 
     /// Opaque WRPR register
-    pub(crate) _wrpr: WRPR,
+    pub(crate) wrpr: WRPR,
 }
 impl Parts {
+    fn unlock(&mut self, bank: Bank) -> Result<()> {
+        // Wait for any ongoing operations
+        while self.sr.sr_bank(bank).read().bsy().bit_is_set() {}
+
+        // NOTE(unsafe) write Keys to the key register. This is safe because the
+        // only side effect of these writes is to unlock the flash control
+        // register, which is the intent of this function. Do not rearrange the
+        // order of these writes or the control register will be permanently
+        // locked out until reset.
+        unsafe {
+            self.keyr.keyr_bank(bank).write(|w| w.key().bits(KEY1));
+        }
+        unsafe {
+            self.keyr.keyr_bank(bank).write(|w| w.key().bits(KEY2));
+        }
+
+        // Verify success
+        match self.cr.cr_bank(bank).read().lock().bit_is_clear() {
+            true => Ok(()),
+            false => Err(Error::UnlockError),
+        }
+    }
+
+    fn lock(&mut self, bank: Bank) -> Result<()> {
+        //Wait for ongoing flash operations
+        while self.sr.sr_bank(bank).read().bsy().bit_is_set() {}
+
+        // Set lock bit
+        self.cr.cr_bank(bank).modify(|_, w| w.lock().set_bit());
+
+        // Verify success
+        match self.cr.cr_bank(bank).read().lock().bit_is_set() {
+            true => Ok(()),
+            false => Err(Error::LockError),
+        }
+    }
+
     pub fn writer(&mut self, sector_sz: SectorSize, flash_sz: FlashSize) -> FlashWriter {
         FlashWriter {
             flash: self,
@@ -358,6 +498,266 @@ impl Parts {
     }
 }
 
+/// Address of the option bytes, each stored as a byte and its complement
+const OPTION_BYTES: u32 = 0x1FFF_F800;
+
+/// Size of the flash covered by one write protection bit
+pub const WRP_GROUP_SIZE: u32 = 4 * SZ_1K as u32;
+
+/// User option bits, loaded at reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserOptions {
+    /// The independent watchdog is started by software, otherwise it runs from reset
+    pub wdg_sw: bool,
+    /// No reset when entering Stop mode
+    pub n_rst_stop: bool,
+    /// No reset when entering Standby mode
+    pub n_rst_stdby: bool,
+}
+
+impl Default for UserOptions {
+    fn default() -> Self {
+        Self {
+            wdg_sw: true,
+            n_rst_stop: true,
+            n_rst_stdby: true,
+        }
+    }
+}
+
+/// Content of the option bytes
+///
+/// The default value is the factory configuration: no protection, user bits and data bytes
+/// erased.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionBytes {
+    /// Read-out protection: the flash can only be read by the code running from it, not by
+    /// the debugger or from SRAM. Disabling it again mass erases the flash.
+    pub read_protection: bool,
+    pub user: UserOptions,
+    /// Two bytes of user data
+    pub data: [u8; 2],
+    /// Write protection, bit `n` protects group `n` of [`WRP_GROUP_SIZE`] bytes, see
+    /// [`wrp_group`]
+    pub write_protection: u32,
+}
+
+impl Default for OptionBytes {
+    fn default() -> Self {
+        Self {
+            read_protection: false,
+            user: UserOptions::default(),
+            data: [0xFF; 2],
+            write_protection: 0,
+        }
+    }
+}
+
+impl OptionBytes {
+    /// Decodes the option bytes loaded in the `OBR` and `WRPR` registers
+    const fn from_registers(obr: u32, wrpr: u32) -> Self {
+        Self {
+            read_protection: obr & (1 << 1) != 0,
+            user: UserOptions {
+                wdg_sw: obr & (1 << 2) != 0,
+                n_rst_stop: obr & (1 << 3) != 0,
+                n_rst_stdby: obr & (1 << 4) != 0,
+            },
+            data: [(obr >> 10) as u8, (obr >> 18) as u8],
+            // Cleared bits protect the pages
+            write_protection: !wrpr,
+        }
+    }
+
+    /// Bytes to program in order: RDP, USER, Data0, Data1, WRP0 to WRP3
+    const fn to_bytes(self) -> [u8; 8] {
+        let wrp = (!self.write_protection).to_le_bytes();
+        [
+            if self.read_protection {
+                0x00
+            } else {
+                RDPRT_KEY
+            },
+            0xF8 | self.user.wdg_sw as u8
+                | (self.user.n_rst_stop as u8) << 1
+                | (self.user.n_rst_stdby as u8) << 2,
+            self.data[0],
+            self.data[1],
+            wrp[0],
+            wrp[1],
+            wrp[2],
+            wrp[3],
+        ]
+    }
+}
+
+/// Returns the write protection bit covering `FLASH_START + offset`
+///
+/// On devices with more than 128 KB of flash, the last bit covers the rest of the flash.
+pub const fn wrp_group(offset: u32) -> u32 {
+    let group = offset / WRP_GROUP_SIZE;
+    if group > 31 {
+        31
+    } else {
+        group
+    }
+}
+
+impl Parts {
+    /// Returns the option bytes loaded at the last reset
+    ///
+    /// Programmed option bytes only apply after
+    /// [`reload_option_bytes`](Self::reload_option_bytes).
+    pub fn option_bytes(&mut self) -> OptionBytes {
+        OptionBytes::from_registers(self.obr.obr().read().bits(), self.wrpr.wrpr().read().bits())
+    }
+
+    /// Erases and programs all the option bytes
+    ///
+    /// Only the bytes different from their erased value are programmed. Enabling the
+    /// read-out protection of the flash, or write protecting the running code, takes effect
+    /// after the next reset, see [`reload_option_bytes`](Self::reload_option_bytes).
+    pub fn program_option_bytes(&mut self, option_bytes: &OptionBytes) -> Result<()> {
+        self.unlock_option_bytes()?;
+        let result = self.write_option_bytes(option_bytes.to_bytes());
+        self.lock_option_bytes()?;
+        result
+    }
+
+    /// Restores the factory option bytes: no protection, user bits and data bytes erased
+    pub fn erase_option_bytes(&mut self) -> Result<()> {
+        self.program_option_bytes(&OptionBytes::default())
+    }
+
+    /// Resets the device to load the programmed option bytes
+    pub fn reload_option_bytes(&mut self) -> ! {
+        // Option bytes are loaded from flash by a system reset only
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    fn unlock_option_bytes(&mut self) -> Result<()> {
+        self.unlock(Bank::Bank1)?;
+
+        // NOTE(unsafe) same as the flash keys, the only side effect of these writes is to
+        // allow writing the option bytes
+        unsafe {
+            self.optkeyr.optkeyr().write(|w| w.optkey().bits(KEY1));
+        }
+        unsafe {
+            self.optkeyr.optkeyr().write(|w| w.optkey().bits(KEY2));
+        }
+
+        if self.cr.cr().read().optwre().bit_is_set() {
+            Ok(())
+        } else {
+            self.lock(Bank::Bank1)?;
+            Err(Error::UnlockError)
+        }
+    }
+
+    fn lock_option_bytes(&mut self) -> Result<()> {
+        while self.sr.sr().read().bsy().bit_is_set() {}
+        self.cr.cr().modify(|_, w| w.optwre().clear_bit());
+        self.lock(Bank::Bank1)
+    }
+
+    fn write_option_bytes(&mut self, bytes: [u8; 8]) -> Result<()> {
+        // Erase all the option bytes at once
+        self.cr.cr().modify(|_, w| w.opter().set_bit());
+        self.cr.cr().modify(|_, w| w.strt().set_bit());
+        // One cycle delay before BSY is set, see `page_erase`
+        cortex_m::asm::nop();
+        while self.sr.sr().read().bsy().bit_is_set() {}
+        self.cr.cr().modify(|_, w| w.opter().clear_bit());
+        self.check_errors(Error::EraseError)?;
+
+        self.cr.cr().modify(|_, w| w.optpg().set_bit());
+        for (idx, &byte) in bytes.iter().enumerate() {
+            if byte == 0xFF {
+                continue;
+            }
+            let address = (OPTION_BYTES + 2 * idx as u32) as *mut u16;
+            // NOTE(unsafe) halfword write in the option bytes area while OPTPG is set, the
+            // complement in the upper byte is computed by the hardware
+            unsafe { core::ptr::write_volatile(address, byte as u16) };
+            while self.sr.sr().read().bsy().bit_is_set() {}
+
+            if let Err(e) = self.check_errors(Error::ProgrammingError) {
+                self.cr.cr().modify(|_, w| w.optpg().clear_bit());
+                return Err(e);
+            }
+            // NOTE(unsafe) read with no side effects within the option bytes
+            let verify = unsafe { core::ptr::read_volatile(address) };
+            if verify != (byte as u16 | (!byte as u16) << 8) {
+                self.cr.cr().modify(|_, w| w.optpg().clear_bit());
+                return Err(Error::VerifyError);
+            }
+        }
+        self.cr.cr().modify(|_, w| w.optpg().clear_bit());
+        Ok(())
+    }
+
+    /// Clears the error flags, returning `error` if one was set
+    fn check_errors(&mut self, error: Error) -> Result<()> {
+        let sr = self.sr.sr().read();
+        if sr.pgerr().bit_is_set() || sr.wrprterr().bit_is_set() {
+            // reset by writing 1
+            self.sr
+                .sr()
+                .modify(|_, w| w.pgerr().bit(true).wrprterr().bit(true));
+            Err(error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn flash_banks() {
+    assert_eq!(Bank::of(0x1_F800), Bank::Bank1);
+    assert_eq!(Bank::Bank1.range(FlashSize::Sz128K), (0, 0x2_0000));
+
+    #[cfg(feature = "xl")]
+    {
+        assert_eq!(Bank::of(0x7_F800), Bank::Bank1);
+        assert_eq!(Bank::of(0x8_0000), Bank::Bank2);
+        assert_eq!(Bank::Bank1.range(FlashSize::Sz1M), (0, 0x8_0000));
+        assert_eq!(Bank::Bank2.range(FlashSize::Sz768K), (0x8_0000, 0xC_0000));
+    }
+}
+
+#[test]
+fn option_bytes_encoding() {
+    let factory = OptionBytes::default();
+    assert_eq!(
+        factory.to_bytes(),
+        [0xA5, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+    );
+
+    let protected = OptionBytes {
+        read_protection: true,
+        user: UserOptions {
+            wdg_sw: false,
+            n_rst_stop: true,
+            n_rst_stdby: false,
+        },
+        data: [0x12, 0x34],
+        write_protection: 1 << wrp_group(0) | 1 << wrp_group(0x2_1000),
+    };
+    assert_eq!(
+        protected.to_bytes(),
+        [0x00, 0xFA, 0x12, 0x34, 0xFE, 0xFF, 0xFF, 0x7F]
+    );
+
+    // OBR with OPTERR clear, RDPRT, nRST_STOP and the data bytes
+    let obr = 1 << 1 | 1 << 3 | 0x12 << 10 | 0x34 << 18;
+    assert_eq!(OptionBytes::from_registers(obr, 0x7FFF_FFFE), protected);
+    assert_eq!(
+        OptionBytes::from_registers(0x03FF_FC1C, 0xFFFF_FFFF),
+        factory
+    );
+}
+
 /// Opaque ACR register
 #[non_exhaustive]
 pub struct ACR;
@@ -380,6 +780,12 @@ impl AR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { (*FLASH::ptr()).ar() }
     }
+
+    /// AR of `bank`, AR2 for the second bank
+    pub(crate) fn ar_bank(&mut self, bank: Bank) -> &flash::AR {
+        // NOTE(unsafe) this proxy grants exclusive access to the register of both banks
+        unsafe { (*bank.regs()).ar() }
+    }
 }
 
 /// Opaque CR register
@@ -392,6 +798,12 @@ impl CR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { (*FLASH::ptr()).cr() }
     }
+
+    /// CR of `bank`, CR2 for the second bank
+    pub(crate) fn cr_bank(&mut self, bank: Bank) -> &flash::CR {
+        // NOTE(unsafe) this proxy grants exclusive access to the register of both banks
+        unsafe { (*bank.regs()).cr() }
+    }
 }
 
 /// Opaque KEYR register
@@ -404,6 +816,12 @@ impl KEYR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { (*FLASH::ptr()).keyr() }
     }
+
+    /// KEYR of `bank`, KEYR2 for the second bank
+    pub(crate) fn keyr_bank(&mut self, bank: Bank) -> &flash::KEYR {
+        // NOTE(unsafe) this proxy grants exclusive access to the register of both banks
+        unsafe { (*bank.regs()).keyr() }
+    }
 }
 
 /// Opaque OBR register
@@ -440,6 +858,12 @@ impl SR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { (*FLASH::ptr()).sr() }
     }
+
+    /// SR of `bank`, SR2 for the second bank
+    pub(crate) fn sr_bank(&mut self, bank: Bank) -> &flash::SR {
+        // NOTE(unsafe) this proxy grants exclusive access to the register of both banks
+        unsafe { (*bank.regs()).sr() }
+    }
 }
 
 /// Opaque WRPR register
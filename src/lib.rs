@@ -144,6 +144,7 @@ pub mod flash;
 pub mod gpio;
 pub mod i2c;
 pub mod prelude;
+pub mod pwr;
 pub mod rcc;
 pub mod rtc;
 pub mod serial;
@@ -17,12 +17,15 @@ pub use crate::gpio::GpioExt as _stm32_hal_gpio_GpioExt;
 pub use crate::hal_02::adc::OneShot as _embedded_hal_adc_OneShot;
 pub use crate::hal_02::prelude::*;
 pub use crate::i2c::I2cExt as _;
+pub use crate::pwr::PwrExt as _stm32_hal_pwr_PwrExt;
 pub use crate::rcc::RccExt as _stm32_hal_rcc_RccExt;
 pub use crate::serial::SerialExt as _;
 pub use crate::spi::SpiExt as _;
 pub use crate::time::U32Ext as _stm32_hal_time_U32Ext;
 pub use crate::timer::pwm_input::PwmInputExt as _;
 pub use crate::timer::pwm_input::QeiExt as _;
+#[cfg(feature = "async")]
+pub use crate::timer::DelayAsyncExt as _stm32f1xx_hal_timer_DelayAsyncExt;
 #[cfg(feature = "rtic")]
 pub use crate::timer::MonoTimerExt as _stm32f4xx_hal_timer_MonoTimerExt;
 pub use crate::timer::PwmExt as _stm32f4xx_hal_timer_PwmExt;
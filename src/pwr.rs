@@ -0,0 +1,306 @@
+//! # Power control (PWR)
+//!
+//! Low-power modes of the core:
+//!
+//! - Sleep: the CPU clock stops, peripherals keep running and any interrupt (or event) wakes
+//!   the core up.
+//! - Stop: all clocks in the 1.8 V domain stop, SRAM and registers are kept. EXTI lines wake the
+//!   device up (with the RTC alarm on line 17), which then runs from HSI until the clock
+//!   configuration is restored.
+//! - Standby: the 1.8 V domain is powered off. Only the backup domain is kept, and the device
+//!   goes through a reset on a rising edge of the WKUP pin (PA0), an RTC alarm, an IWDG reset or
+//!   an external reset.
+//!
+//! ```rust,ignore
+//! let mut pwr = dp.PWR.constrain();
+//! let mut scb = cp.SCB;
+//!
+//! if pwr.is_standby_wakeup() {
+//!     pwr.clear_wakeup_flags();
+//! }
+//!
+//! pwr.stop(&mut scb, Regulator::LowPower, LowPowerEntry::Wfi);
+//! // HSE, PLL and SYSCLK source are running again as frozen in `clocks`
+//!
+//! pwr.enable_wakeup_pin(true);
+//! pwr.standby(&mut scb);
+//! ```
+//!
+//! The programmable voltage detector (PVD) compares VDD with a threshold and raises EXTI
+//! line 16, e.g. to save state to flash before the supply collapses:
+//!
+//! ```rust,ignore
+//! pwr.enable_pvd(PvdLevel::V2_9);
+//! pwr.listen_pvd(&mut dp.EXTI, Edge::Rising);
+//! unsafe { NVIC::unmask(pac::Interrupt::PVD) };
+//!
+//! #[interrupt]
+//! fn PVD() {
+//!     Pwr::clear_pvd_interrupt();
+//!     // VDD dropped below 2.9 V
+//! }
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+use cortex_m::peripheral::SCB;
+
+use crate::gpio::Edge;
+use crate::pac::{EXTI, PWR, RCC};
+use crate::rcc::Enable;
+
+/// Extension trait that constrains the `PWR` peripheral
+pub trait PwrExt {
+    /// Enables the power interface clock and wraps the peripheral
+    fn constrain(self) -> Pwr;
+}
+
+impl PwrExt for PWR {
+    fn constrain(self) -> Pwr {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        PWR::enable(rcc);
+
+        Pwr { pwr: self }
+    }
+}
+
+/// Voltage regulator state in Stop mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Regulator {
+    /// Regulator on, for a faster wakeup
+    On,
+    /// Regulator in low-power mode, for a lower consumption
+    LowPower,
+}
+
+/// Instruction used to enter a low-power mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LowPowerEntry {
+    /// Wait for interrupt, wakes up on any enabled interrupt
+    Wfi,
+    /// Wait for event, wakes up on an event (e.g. an EXTI event line or `SEVONPEND`)
+    Wfe,
+}
+
+impl LowPowerEntry {
+    fn enter(self) {
+        match self {
+            Self::Wfi => cortex_m::asm::wfi(),
+            Self::Wfe => cortex_m::asm::wfe(),
+        }
+    }
+}
+
+/// Threshold of the programmable voltage detector, on falling VDD
+///
+/// The threshold on rising VDD is about 100 mV higher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PvdLevel {
+    /// 2.2 V
+    V2_2 = 0,
+    /// 2.3 V
+    V2_3 = 1,
+    /// 2.4 V
+    V2_4 = 2,
+    /// 2.5 V
+    V2_5 = 3,
+    /// 2.6 V
+    V2_6 = 4,
+    /// 2.7 V
+    V2_7 = 5,
+    /// 2.8 V
+    V2_8 = 6,
+    /// 2.9 V
+    V2_9 = 7,
+}
+
+/// Constrained PWR peripheral
+///
+/// Dereferences to the `PWR` registers, e.g. for
+/// [`BKP::constrain`](crate::rcc::BKP::constrain).
+pub struct Pwr {
+    pwr: PWR,
+}
+
+impl Deref for Pwr {
+    type Target = PWR;
+    fn deref(&self) -> &Self::Target {
+        &self.pwr
+    }
+}
+
+impl DerefMut for Pwr {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.pwr
+    }
+}
+
+impl Pwr {
+    /// Releases the PWR peripheral
+    pub fn release(self) -> PWR {
+        self.pwr
+    }
+
+    /// Goes back to Sleep mode when returning from an interrupt handler to thread mode
+    ///
+    /// The main loop then only runs until the first interrupt.
+    pub fn sleep_on_exit(&mut self, scb: &mut SCB, enable: bool) {
+        if enable {
+            scb.set_sleeponexit();
+        } else {
+            scb.clear_sleeponexit();
+        }
+    }
+
+    /// Enters Sleep mode until an interrupt or an event
+    pub fn sleep(&mut self, scb: &mut SCB, entry: LowPowerEntry) {
+        scb.clear_sleepdeep();
+        entry.enter();
+    }
+
+    /// Enters Stop mode until an EXTI interrupt or event
+    ///
+    /// The device wakes up running from HSI. HSE, the clock security system, the PLLs and the
+    /// system clock source enabled before are then restored and waited for, so the frozen
+    /// `Clocks` stay valid. Pending EXTI lines and the RTC alarm flag must be cleared before, or
+    /// the device does not stop.
+    pub fn stop(&mut self, scb: &mut SCB, regulator: Regulator, entry: LowPowerEntry) {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let cr = rcc.cr().read();
+        let sw = rcc.cfgr().read().sw().bits();
+
+        self.pwr.cr().modify(|_, w| {
+            w.pdds().clear_bit();
+            w.lpds().bit(regulator == Regulator::LowPower)
+        });
+        scb.set_sleepdeep();
+        entry.enter();
+        scb.clear_sleepdeep();
+
+        if cr.hseon().bit_is_set() {
+            rcc.cr().modify(|_, w| w.hseon().set_bit());
+            while rcc.cr().read().hserdy().bit_is_clear() {}
+            // The clock security system can only be enabled again once HSE is ready
+            if cr.csson().bit_is_set() {
+                rcc.cr().modify(|_, w| w.csson().set_bit());
+            }
+        }
+
+        #[cfg(feature = "connectivity")]
+        {
+            if cr.pll2on().bit_is_set() {
+                rcc.cr().modify(|_, w| w.pll2on().set_bit());
+                while rcc.cr().read().pll2rdy().bit_is_clear() {}
+            }
+            if cr.pll3on().bit_is_set() {
+                rcc.cr().modify(|_, w| w.pll3on().set_bit());
+                while rcc.cr().read().pll3rdy().bit_is_clear() {}
+            }
+        }
+
+        if cr.pllon().bit_is_set() {
+            rcc.cr().modify(|_, w| w.pllon().set_bit());
+            while rcc.cr().read().pllrdy().bit_is_clear() {}
+        }
+
+        #[allow(unused_unsafe)]
+        rcc.cfgr().modify(|_, w| unsafe { w.sw().bits(sw) });
+        while rcc.cfgr().read().sws().bits() != sw {}
+    }
+
+    /// Enters Standby mode, the device resets when it wakes up
+    ///
+    /// The wakeup flag is cleared first, see [`enable_wakeup_pin`](Self::enable_wakeup_pin) to
+    /// wake up on the WKUP pin. A pending RTC alarm flag must be cleared before with
+    /// `Rtc::clear_alarm_flag`, and the WKUP pin must be low, or the device wakes up at once.
+    pub fn standby(&mut self, scb: &mut SCB) -> ! {
+        self.pwr.cr().modify(|_, w| {
+            w.cwuf().set_bit();
+            w.pdds().set_bit()
+        });
+        scb.set_sleepdeep();
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
+    /// Wakes up from Standby mode on a rising edge of the WKUP pin (PA0)
+    ///
+    /// The pin is forced to input pull-down while enabled.
+    pub fn enable_wakeup_pin(&mut self, enable: bool) {
+        self.pwr.csr().modify(|_, w| w.ewup().bit(enable));
+    }
+
+    /// Returns `true` if the device was in Standby mode before the last reset
+    pub fn is_standby_wakeup(&self) -> bool {
+        self.pwr.csr().read().sbf().bit_is_set()
+    }
+
+    /// Returns `true` if a wakeup event occurred: WKUP pin rising edge or RTC alarm
+    ///
+    /// The flag is also set when the WKUP pin is enabled while it is high.
+    pub fn is_woken_up(&self) -> bool {
+        self.pwr.csr().read().wuf().bit_is_set()
+    }
+
+    /// Clears the standby and wakeup flags
+    pub fn clear_wakeup_flags(&mut self) {
+        self.pwr.cr().modify(|_, w| {
+            w.csbf().set_bit();
+            w.cwuf().set_bit()
+        });
+    }
+
+    /// Enables the programmable voltage detector with a threshold
+    pub fn enable_pvd(&mut self, level: PvdLevel) {
+        self.pwr
+            .cr()
+            .modify(|_, w| unsafe { w.pls().bits(level as u8) });
+        self.pwr.cr().modify(|_, w| w.pvde().set_bit());
+    }
+
+    /// Disables the programmable voltage detector
+    pub fn disable_pvd(&mut self) {
+        self.pwr.cr().modify(|_, w| w.pvde().clear_bit());
+    }
+
+    /// Returns `true` if VDD is below the PVD threshold (`PVDO` flag)
+    pub fn is_vdd_low(&self) -> bool {
+        self.pwr.csr().read().pvdo().bit_is_set()
+    }
+
+    /// Enables the PVD interrupt (EXTI line 16)
+    ///
+    /// [`Edge::Rising`] triggers when VDD falls below the threshold, [`Edge::Falling`] when it
+    /// rises above it again.
+    pub fn listen_pvd(&mut self, exti: &mut EXTI, edge: Edge) {
+        exti.rtsr().modify(|_, w| {
+            w.tr16()
+                .bit(matches!(edge, Edge::Rising | Edge::RisingFalling))
+        });
+        exti.ftsr().modify(|_, w| {
+            w.tr16()
+                .bit(matches!(edge, Edge::Falling | Edge::RisingFalling))
+        });
+        Self::clear_pvd_interrupt();
+        exti.imr().modify(|_, w| w.mr16().set_bit());
+    }
+
+    /// Disables the PVD interrupt
+    pub fn unlisten_pvd(&mut self, exti: &mut EXTI) {
+        exti.imr().modify(|_, w| w.mr16().clear_bit());
+    }
+
+    /// Returns `true` if the PVD interrupt is pending
+    pub fn is_pvd_interrupt() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*EXTI::ptr()).pr().read().pr16().bit_is_set() }
+    }
+
+    /// Clears the PVD interrupt, to be called from the `PVD` interrupt handler
+    pub fn clear_pvd_interrupt() {
+        // NOTE(unsafe) write 1 to clear of the line 16 pending bit only
+        unsafe { (*EXTI::ptr()).pr().write(|w| w.pr16().clear_bit_by_one()) };
+    }
+}
@@ -8,8 +8,17 @@ use crate::time::MHz;
 use fugit::{HertzU32 as Hertz, RateExtU32};
 
 use crate::backup_domain::BackupDomain;
+use crate::watchdog::{IndependentWatchdog, IwdgConfig};
+use core::sync::atomic::{AtomicU32, Ordering};
 
+mod css;
 mod enable;
+mod lsclk;
+mod mco;
+
+pub use css::*;
+pub use lsclk::*;
+pub use mco::*;
 
 /// Extension trait that constrains the `RCC` peripheral
 pub trait RccExt {
@@ -20,16 +29,10 @@ pub trait RccExt {
 impl RccExt for RCC {
     fn constrain(self) -> Rcc {
         Rcc {
-            cfgr: CFGR {
-                hse: None,
-                hse_bypass: false,
-                hclk: None,
-                pclk1: None,
-                pclk2: None,
-                sysclk: None,
-                adcclk: None,
-            },
+            cfgr: CFGR::new(),
             bkp: BKP,
+            csr: CSR,
+            reset_reason: ResetReason::read_and_clear(),
         }
     }
 }
@@ -46,6 +49,125 @@ impl RccExt for RCC {
 pub struct Rcc {
     pub cfgr: CFGR,
     pub bkp: BKP,
+    /// LSI control, see [`CSR::start_lsi`]
+    pub csr: CSR,
+    /// Cause of the last reset, read when the RCC is constrained
+    pub reset_reason: ResetReason,
+}
+
+impl Rcc {
+    /// Starts the independent watchdog, e.g. before the clocks are frozen
+    ///
+    /// The watchdog runs from LSI, which the hardware turns on by itself, so it can cover a
+    /// clock setup that hangs waiting for HSE or the PLL.
+    pub fn start_iwdg(
+        &mut self,
+        iwdg: crate::pac::IWDG,
+        config: IwdgConfig,
+    ) -> IndependentWatchdog {
+        let mut watchdog = IndependentWatchdog::new(iwdg);
+        watchdog.start_with(config);
+        watchdog
+    }
+}
+
+/// Switches to another clock configuration after [`freeze`](CFGR::freeze)
+///
+/// SYSCLK runs from HSI while HSE and the PLLs are restarted, and the flash latency is
+/// adjusted. The `&mut ACR` borrow keeps two reconfigurations from running at once.
+///
+/// This is not a method of [`Rcc`]: `freeze` consumes `rcc.cfgr`, so `Rcc` is partially moved
+/// afterwards and none of its `&mut self` methods can be called. `ACR` is still held by the
+/// application after `freeze` and is needed anyway to change the flash latency.
+///
+/// All the `Clocks` returned before become stale. Nothing in the HAL checks for this: the
+/// peripherals configured with them must be set up again by the application, e.g. with
+/// `Serial::reconfigure` or by creating the timers again. [`Clocks::is_current`] tells whether
+/// a stored `Clocks` is outdated.
+///
+/// ```rust,ignore
+/// let clocks = rcc.cfgr.freeze(&mut flash.acr);
+/// // Drop to 8 MHz
+/// let clocks = rcc::reconfigure(Config::default(), &mut flash.acr);
+/// // And back to 72 MHz
+/// let cfgr = CFGR::new().use_hse(8.MHz()).sysclk(72.MHz()).pclk1(36.MHz());
+/// let clocks = rcc::reconfigure(Config::from_cfgr(cfgr), &mut flash.acr);
+/// ```
+pub fn reconfigure(cfg: Config, acr: &mut ACR) -> Clocks {
+    // NOTE(unsafe) after freeze, the clock configuration is only written through `&mut ACR`
+    let rcc = unsafe { &*RCC::ptr() };
+
+    // Run from HSI, which is valid with any flash latency
+    rcc.cr().modify(|_, w| w.hsion().set_bit());
+    while rcc.cr().read().hsirdy().bit_is_clear() {}
+    #[allow(unused_unsafe)]
+    rcc.cfgr().modify(|_, w| unsafe { w.sw().bits(0b00) });
+    while rcc.cfgr().read().sws().bits() != 0b00 {}
+
+    // The PLL configuration and the HSE bypass can only be written while they are off
+    rcc.cr().modify(|_, w| {
+        #[cfg(feature = "connectivity")]
+        w.pll2on().clear_bit().pll3on().clear_bit();
+        w.pllon().clear_bit()
+    });
+    rcc.cr()
+        .modify(|_, w| w.csson().clear_bit().hseon().clear_bit());
+    while rcc.cr().read().hserdy().bit_is_set() {}
+    rcc.cr().modify(|_, w| w.hsebyp().clear_bit());
+
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    let clocks = CFGR::_freeze_with_config(cfg, acr);
+    while rcc.cfgr().read().sws().bits() != rcc.cfgr().read().sw().bits() {}
+    clocks
+}
+
+/// Cause of the last reset
+///
+/// The reset flags of `RCC_CSR` are read and cleared by
+/// [constrain](../trait.RccExt.html#tymethod.constrain), so call it early at startup. When
+/// several flags are set, the most specific cause is reported: watchdog and software resets
+/// also drive the NRST pin, and a power-on reset also sets the pin reset flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    /// Power-on or power-down reset, including a brownout of VDD
+    PowerOn,
+    /// Low level on the NRST pin
+    Pin,
+    /// Software reset (`SYSRESETREQ`)
+    Software,
+    /// Independent watchdog reset
+    IndependentWatchdog,
+    /// Window watchdog reset
+    WindowWatchdog,
+    /// Entering Standby or Stop mode with the `nRST_STDBY` or `nRST_STOP` option bit cleared
+    LowPower,
+    /// No reset flag set, e.g. the flags have been cleared by a bootloader
+    Unknown,
+}
+
+impl ResetReason {
+    fn read_and_clear() -> Self {
+        let rcc = unsafe { &*RCC::ptr() };
+        let csr = rcc.csr().read();
+        let reason = if csr.lpwrrstf().bit_is_set() {
+            Self::LowPower
+        } else if csr.wwdgrstf().bit_is_set() {
+            Self::WindowWatchdog
+        } else if csr.iwdgrstf().bit_is_set() {
+            Self::IndependentWatchdog
+        } else if csr.sftrstf().bit_is_set() {
+            Self::Software
+        } else if csr.porrstf().bit_is_set() {
+            Self::PowerOn
+        } else if csr.pinrstf().bit_is_set() {
+            Self::Pin
+        } else {
+            Self::Unknown
+        };
+        rcc.csr().modify(|_, w| w.rmvf().clear());
+        reason
+    }
 }
 
 /// AMBA High-performance Bus (AHB) registers
@@ -114,14 +236,43 @@ pub struct CFGR {
     pclk2: Option<u32>,
     sysclk: Option<u32>,
     adcclk: Option<u32>,
+    css: bool,
+    #[cfg(feature = "connectivity")]
+    pll2clk: Option<u32>,
+    #[cfg(feature = "connectivity")]
+    pll3clk: Option<u32>,
+    #[cfg(feature = "connectivity")]
+    i2s_pll3: bool,
+    usb: bool,
 }
 
 impl CFGR {
+    /// Creates an empty configuration: HSI at 8 MHz without prescalers
+    pub const fn new() -> Self {
+        Self {
+            hse: None,
+            hse_bypass: false,
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            sysclk: None,
+            adcclk: None,
+            css: false,
+            #[cfg(feature = "connectivity")]
+            pll2clk: None,
+            #[cfg(feature = "connectivity")]
+            pll3clk: None,
+            #[cfg(feature = "connectivity")]
+            i2s_pll3: false,
+            usb: false,
+        }
+    }
+
     /// Uses HSE (external oscillator) instead of HSI (internal RC oscillator) as the clock source.
     /// Will result in a hang if an external oscillator is not connected or it fails to start.
     /// The frequency specified must be the frequency of the external oscillator
     #[inline(always)]
-    pub fn use_hse(mut self, freq: Hertz) -> Self {
+    pub const fn use_hse(mut self, freq: Hertz) -> Self {
         self.hse = Some(freq.raw());
         self
     }
@@ -133,48 +284,97 @@ impl CFGR {
     /// frequency specified in the call to use_hse(), and the OSC_OUT pin should not be connected.
     ///
     /// This function has no effect unless use_hse() is also called.
-    pub fn bypass_hse_oscillator(self) -> Self {
+    pub const fn bypass_hse_oscillator(self) -> Self {
         Self {
             hse_bypass: true,
             ..self
         }
     }
 
+    /// Enables the clock security system, which switches to HSI and raises the NMI if HSE fails
+    ///
+    /// See [`handle_css_failure`]. This function has no effect unless use_hse() is also called.
+    pub const fn enable_css(self) -> Self {
+        Self { css: true, ..self }
+    }
+
     /// Sets the desired frequency for the HCLK clock
     #[inline(always)]
-    pub fn hclk(mut self, freq: Hertz) -> Self {
+    pub const fn hclk(mut self, freq: Hertz) -> Self {
         self.hclk = Some(freq.raw());
         self
     }
 
     /// Sets the desired frequency for the PCKL1 clock
     #[inline(always)]
-    pub fn pclk1(mut self, freq: Hertz) -> Self {
+    pub const fn pclk1(mut self, freq: Hertz) -> Self {
         self.pclk1 = Some(freq.raw());
         self
     }
 
     /// Sets the desired frequency for the PCLK2 clock
     #[inline(always)]
-    pub fn pclk2(mut self, freq: Hertz) -> Self {
+    pub const fn pclk2(mut self, freq: Hertz) -> Self {
         self.pclk2 = Some(freq.raw());
         self
     }
 
     /// Sets the desired frequency for the SYSCLK clock
     #[inline(always)]
-    pub fn sysclk(mut self, freq: Hertz) -> Self {
+    pub const fn sysclk(mut self, freq: Hertz) -> Self {
         self.sysclk = Some(freq.raw());
         self
     }
 
     /// Sets the desired frequency for the ADCCLK clock
     #[inline(always)]
-    pub fn adcclk(mut self, freq: Hertz) -> Self {
+    pub const fn adcclk(mut self, freq: Hertz) -> Self {
         self.adcclk = Some(freq.raw());
         self
     }
 
+    /// Sets the desired frequency of PLL2 (40 to 74 MHz), clocked by HSE through PREDIV2
+    ///
+    /// PLL2 can then clock the main PLL through PREDIV1, e.g. to get 72 MHz from a 25 MHz
+    /// crystal. This function has no effect unless use_hse() is also called.
+    #[cfg(feature = "connectivity")]
+    pub const fn pll2clk(mut self, freq: Hertz) -> Self {
+        self.pll2clk = Some(freq.raw());
+        self
+    }
+
+    /// Sets the desired frequency of PLL3 (40 to 74 MHz), clocked by HSE through PREDIV2
+    ///
+    /// PLL3 can clock the Ethernet PHY through MCO (50 MHz RMII clock) and the I2S
+    /// peripherals. This function has no effect unless use_hse() is also called.
+    #[cfg(feature = "connectivity")]
+    pub const fn pll3clk(mut self, freq: Hertz) -> Self {
+        self.pll3clk = Some(freq.raw());
+        self
+    }
+
+    /// Clocks I2S2 and I2S3 from the PLL3 VCO (twice the PLL3 frequency) instead of SYSCLK
+    #[cfg(feature = "connectivity")]
+    pub const fn i2s_from_pll3(self) -> Self {
+        Self {
+            i2s_pll3: true,
+            ..self
+        }
+    }
+
+    /// Requires a valid 48 MHz USB clock, checked by [`try_freeze`](Self::try_freeze)
+    #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+    pub const fn require_usb(self) -> Self {
+        Self { usb: true, ..self }
+    }
+
+    /// Applies the clock configuration like [`freeze`](Self::freeze), if the requested
+    /// frequencies can be reached exactly and are within the limits of the device
+    pub fn try_freeze(self, acr: &mut ACR) -> Result<Clocks, ClockError> {
+        let cfg = Config::try_from_cfgr(self, ClockLimits::DEVICE)?;
+        Ok(Self::_freeze_with_config(cfg, acr))
+    }
+
     /// Applies the clock configuration and returns a `Clocks` struct that signifies that the
     /// clocks are frozen, and contains the frequencies used. After this function is called,
     /// the clocks can not change
@@ -228,6 +428,31 @@ impl CFGR {
             });
 
             while rcc.cr().read().hserdy().bit_is_clear() {}
+
+            rcc.cr().modify(|_, w| w.csson().bit(cfg.css));
+        }
+
+        #[cfg(feature = "connectivity")]
+        {
+            rcc.cfgr2().write(|w| unsafe {
+                w.prediv1().bits(cfg.prediv1 - 1);
+                w.prediv2().bits(cfg.prediv2 - 1);
+                w.pll2mul().bits(pll23mul_bits(cfg.pll2mul.unwrap_or(8)));
+                w.pll3mul().bits(pll23mul_bits(cfg.pll3mul.unwrap_or(8)));
+                w.prediv1src().bit(cfg.prediv1src == Prediv1Src::Pll2);
+                w.i2s2src().bit(cfg.i2s_pll3);
+                w.i2s3src().bit(cfg.i2s_pll3)
+            });
+
+            if cfg.pll2mul.is_some() {
+                rcc.cr().modify(|_, w| w.pll2on().set_bit());
+                while rcc.cr().read().pll2rdy().bit_is_clear() {}
+            }
+
+            if cfg.pll3mul.is_some() {
+                rcc.cr().modify(|_, w| w.pll3on().set_bit());
+                while rcc.cr().read().pll3rdy().bit_is_clear() {}
+            }
         }
 
         if let Some(pllmul_bits) = cfg.pllmul {
@@ -235,6 +460,8 @@ impl CFGR {
 
             #[allow(unused_unsafe)]
             rcc.cfgr().modify(|_, w| unsafe {
+                #[cfg(not(feature = "connectivity"))]
+                w.pllxtpre().bit(cfg.pllxtpre);
                 w.pllmul().bits(pllmul_bits).pllsrc().bit(cfg.hse.is_some())
             });
 
@@ -333,9 +560,14 @@ impl BKP {
     }
 }
 
+/// Control/status register (CSR), owns the LSI control bits
+#[non_exhaustive]
+pub struct CSR;
+
 /// Frozen clock frequencies
 ///
-/// The existence of this value indicates that the clock configuration can no longer be changed
+/// The existence of this value indicates that the clock configuration can only be changed with
+/// [`reconfigure`] or by the clock security system, which make it stale
 ///
 /// To acquire it, use the freeze function on the `rcc.cfgr` register. If desired, you can adjust
 /// the frequencies using the methods on [cfgr](struct.CFGR.html) before calling freeze.
@@ -358,9 +590,26 @@ pub struct Clocks {
     adcclk: Hertz,
     #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
     usbclk_valid: bool,
+    #[cfg(feature = "connectivity")]
+    pll2clk: Option<Hertz>,
+    #[cfg(feature = "connectivity")]
+    pll3clk: Option<Hertz>,
+    generation: u32,
 }
 
+/// Incremented each time the clocks are reconfigured
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
 impl Clocks {
+    /// Returns `false` if the clock configuration changed since these frequencies were returned
+    ///
+    /// This is only a query, no driver calls it. After [`reconfigure`] or a clock security
+    /// system failure, the drivers set up with stale `Clocks` run with wrong baud rates or timer
+    /// periods until the application configures them again with the new `Clocks`.
+    pub fn is_current(&self) -> bool {
+        self.generation == GENERATION.load(Ordering::Relaxed)
+    }
+
     /// Returns the frequency of the AHB
     pub const fn hclk(&self) -> Hertz {
         self.hclk
@@ -411,6 +660,18 @@ impl Clocks {
     pub const fn usbclk_valid(&self) -> bool {
         self.usbclk_valid
     }
+
+    /// Returns the frequency of PLL2, if enabled
+    #[cfg(feature = "connectivity")]
+    pub const fn pll2clk(&self) -> Option<Hertz> {
+        self.pll2clk
+    }
+
+    /// Returns the frequency of PLL3, if enabled
+    #[cfg(feature = "connectivity")]
+    pub const fn pll3clk(&self) -> Option<Hertz> {
+        self.pll3clk
+    }
 }
 
 /// Frequency on bus that peripheral is connected in
@@ -503,23 +764,287 @@ pub struct Config {
     pub usbpre: UsbPre,
     pub adcpre: AdcPre,
     pub allow_overclock: bool,
+    /// Clock security system on HSE
+    pub css: bool,
+    /// HSE divided by 2 before the PLL
+    #[cfg(not(feature = "connectivity"))]
+    pub pllxtpre: bool,
+    /// PREDIV1 divider, 1 to 16
+    #[cfg(feature = "connectivity")]
+    pub prediv1: u8,
+    #[cfg(feature = "connectivity")]
+    pub prediv1src: Prediv1Src,
+    /// PREDIV2 divider, 1 to 16
+    #[cfg(feature = "connectivity")]
+    pub prediv2: u8,
+    /// PLL2 multiplier, 8 to 14, 16 or 20
+    #[cfg(feature = "connectivity")]
+    pub pll2mul: Option<u8>,
+    /// PLL3 multiplier, 8 to 14, 16 or 20
+    #[cfg(feature = "connectivity")]
+    pub pll3mul: Option<u8>,
+    /// I2S2 and I2S3 clocked by the PLL3 VCO instead of SYSCLK
+    #[cfg(feature = "connectivity")]
+    pub i2s_pll3: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
-        Self {
-            hse: None,
-            hse_bypass: false,
-            pllmul: None,
-            hpre: HPre::Div1,
-            ppre1: PPre::Div1,
-            ppre2: PPre::Div1,
-            #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
-            usbpre: UsbPre::Div1_5,
-            adcpre: AdcPre::Div2,
-            allow_overclock: false,
+        Self::RESET
+    }
+}
+
+impl Config {
+    /// HSI without PLL nor prescalers
+    const RESET: Self = Self {
+        hse: None,
+        hse_bypass: false,
+        pllmul: None,
+        hpre: HPre::Div1,
+        ppre1: PPre::Div1,
+        ppre2: PPre::Div1,
+        #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+        usbpre: UsbPre::Div1_5,
+        adcpre: AdcPre::Div2,
+        allow_overclock: false,
+        css: false,
+        #[cfg(not(feature = "connectivity"))]
+        pllxtpre: false,
+        #[cfg(feature = "connectivity")]
+        prediv1: 1,
+        #[cfg(feature = "connectivity")]
+        prediv1src: Prediv1Src::Hse,
+        #[cfg(feature = "connectivity")]
+        prediv2: 1,
+        #[cfg(feature = "connectivity")]
+        pll2mul: None,
+        #[cfg(feature = "connectivity")]
+        pll3mul: None,
+        #[cfg(feature = "connectivity")]
+        i2s_pll3: false,
+    };
+}
+
+/// Input of PREDIV1, which clocks the main PLL on connectivity line devices
+#[cfg(feature = "connectivity")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prediv1Src {
+    Hse,
+    Pll2,
+}
+
+/// Multipliers of PLL2 and PLL3
+#[cfg(feature = "connectivity")]
+const PLL23MUL: [u8; 9] = [8, 9, 10, 11, 12, 13, 14, 16, 20];
+
+#[cfg(feature = "connectivity")]
+const fn pll23mul_bits(mul: u8) -> u8 {
+    match mul {
+        16 => 0b1110,
+        20 => 0b1111,
+        _ => mul - 2,
+    }
+}
+
+/// Returns the PLL2 or PLL3 multiplier closest to `target` and the error in Hz
+#[cfg(feature = "connectivity")]
+const fn pll23_search(input: u32, target: Option<u32>) -> (Option<u8>, u32) {
+    let target = if let Some(target) = target {
+        target
+    } else {
+        return (None, 0);
+    };
+    let mut best = (None, u32::MAX);
+    let mut i = 0;
+    while i < PLL23MUL.len() {
+        let out = input * PLL23MUL[i] as u32;
+        let err = if out > target {
+            out - target
+        } else {
+            target - out
+        };
+        if out >= 40_000_000 && out <= 74_000_000 && err < best.1 {
+            best = (Some(PLL23MUL[i]), err);
+        }
+        i += 1;
+    }
+    best
+}
+
+/// Returns the PREDIV2 divider and the PLL2 and PLL3 multipliers closest to the targets
+///
+/// The PLL2 and PLL3 input must be between 3 and 5 MHz.
+#[cfg(feature = "connectivity")]
+const fn prediv2_search(
+    hse: u32,
+    pll2: Option<u32>,
+    pll3: Option<u32>,
+) -> (u8, Option<u8>, Option<u8>) {
+    let mut best = (1, None, None);
+    if pll2.is_none() && pll3.is_none() {
+        return best;
+    }
+    let mut best_err = u32::MAX;
+    let mut prediv2 = 1;
+    while prediv2 <= 16 {
+        let input = hse / prediv2;
+        if hse % prediv2 == 0 && input >= 3_000_000 && input <= 5_000_000 {
+            let (pll2mul, err2) = pll23_search(input, pll2);
+            let (pll3mul, err3) = pll23_search(input, pll3);
+            let err = err2.saturating_add(err3);
+            if err < best_err {
+                best = (prediv2 as u8, pll2mul, pll3mul);
+                best_err = err;
+            }
         }
+        prediv2 += 1;
     }
+    best
+}
+
+/// Returns PREDIV2, the PLL2 and PLL3 multipliers and the PLL2 frequency for `cfgr`
+#[cfg(feature = "connectivity")]
+const fn pll23_setup(cfgr: &CFGR) -> (u8, Option<u8>, Option<u8>, Option<u32>) {
+    if let Some(hse) = cfgr.hse {
+        let (prediv2, pll2mul, pll3mul) = prediv2_search(hse, cfgr.pll2clk, cfgr.pll3clk);
+        let pll2clk = if let Some(pll2mul) = pll2mul {
+            Some(hse / prediv2 as u32 * pll2mul as u32)
+        } else {
+            None
+        };
+        (prediv2, pll2mul, pll3mul, pll2clk)
+    } else {
+        (1, None, None, None)
+    }
+}
+
+/// Returns the PREDIV1 divider and source and the PLL multiplier bits giving the highest
+/// frequency not above `sysclk`, or the lowest one if none is
+///
+/// The PLL input must be between 3 and 12 MHz, and the output at least 18 MHz.
+#[cfg(feature = "connectivity")]
+const fn prediv1_search(hse: u32, pll2: Option<u32>, sysclk: u32) -> (u8, Prediv1Src, u8, u32) {
+    // x4 to x9 and x6.5, as register bits
+    const PLLMUL_BITS: [u8; 7] = [2, 3, 4, 5, 6, 7, 13];
+
+    let mut best = (1, Prediv1Src::Hse, 2, u32::MAX);
+    let mut below = false;
+    let mut src = 0;
+    while src < 2 {
+        let (input, prediv1src) = match (src, pll2) {
+            (0, _) => (hse, Prediv1Src::Hse),
+            (_, Some(pll2)) => (pll2, Prediv1Src::Pll2),
+            _ => break,
+        };
+        let mut prediv1 = 1;
+        while prediv1 <= 16 {
+            let pllsrcclk = input / prediv1;
+            if input % prediv1 == 0 && pllsrcclk >= 3_000_000 && pllsrcclk <= 12_000_000 {
+                let mut i = 0;
+                while i < PLLMUL_BITS.len() {
+                    let bits = PLLMUL_BITS[i];
+                    let out = pll_output(pllsrcclk, bits);
+                    // Minimum PLL output
+                    let better = if out < 18_000_000 {
+                        false
+                    } else if out <= sysclk {
+                        !below || out > best.3
+                    } else {
+                        !below && out < best.3
+                    };
+                    if better {
+                        best = (prediv1 as u8, prediv1src, bits, out);
+                        below = out <= sysclk;
+                    }
+                    i += 1;
+                }
+            }
+            prediv1 += 1;
+        }
+        src += 1;
+    }
+    best
+}
+
+/// Returns the PLL multiplier bits and the HSE / 2 source reaching `sysclk` exactly, trying
+/// HSE, then HSE / 2, or HSI / 2 without HSE
+#[cfg(not(feature = "connectivity"))]
+const fn pll_search(hse: Option<u32>, sysclk: u32) -> Option<(u8, bool)> {
+    // Minimum PLL output
+    if sysclk < 16_000_000 {
+        return None;
+    }
+    let mut src = 0;
+    while src < 3 {
+        let (input, pllxtpre) = match (src, hse) {
+            (0, Some(hse)) => (hse, false),
+            (1, Some(hse)) if hse % 2 == 0 => (hse / 2, true),
+            (2, None) => (HSI / 2, false),
+            _ => (0, false),
+        };
+        if input != 0 && sysclk % input == 0 {
+            let pllmul = sysclk / input;
+            if pllmul >= 2 && pllmul <= 16 {
+                return Some((pllmul as u8 - 2, pllxtpre));
+            }
+        }
+        src += 1;
+    }
+    None
+}
+
+/// Returns the PREDIV1 divider and source and the PLL multiplier bits reaching `sysclk`
+/// exactly, trying PREDIV1 from HSE, then from PLL2, or HSI / 2 without HSE
+#[cfg(feature = "connectivity")]
+const fn pll_search(
+    hse: Option<u32>,
+    pll2: Option<u32>,
+    sysclk: u32,
+) -> Option<(u8, Prediv1Src, u8)> {
+    // x4 to x9 and x6.5, as register bits
+    const PLLMUL_BITS: [u8; 7] = [2, 3, 4, 5, 6, 7, 13];
+
+    // Minimum PLL output
+    if sysclk < 18_000_000 {
+        return None;
+    }
+    let mut src = 0;
+    while src < 3 {
+        let (input, prediv1src, max_prediv1) = match (src, hse, pll2) {
+            (0, Some(hse), _) => (hse, Prediv1Src::Hse, 16),
+            (1, Some(_), Some(pll2)) => (pll2, Prediv1Src::Pll2, 16),
+            // HSI / 2 does not go through PREDIV1
+            (2, None, _) => (HSI / 2, Prediv1Src::Hse, 1),
+            _ => (0, Prediv1Src::Hse, 0),
+        };
+        let mut prediv1 = 1;
+        while prediv1 <= max_prediv1 {
+            let pllsrcclk = input / prediv1;
+            if input % prediv1 == 0 && pllsrcclk >= 3_000_000 && pllsrcclk <= 12_000_000 {
+                let mut i = 0;
+                while i < PLLMUL_BITS.len() {
+                    let bits = PLLMUL_BITS[i];
+                    if (bits != 13 || pllsrcclk % 2 == 0) && pll_output(pllsrcclk, bits) == sysclk {
+                        return Some((prediv1 as u8, prediv1src, bits));
+                    }
+                    i += 1;
+                }
+            }
+            prediv1 += 1;
+        }
+        src += 1;
+    }
+    None
+}
+
+/// PLL output frequency for the PLLMUL register bits
+const fn pll_output(pllsrcclk: u32, pllmul_bits: u8) -> u32 {
+    #[cfg(feature = "connectivity")]
+    if pllmul_bits == 13 {
+        // x6.5
+        return pllsrcclk / 2 * 13;
+    }
+    pllsrcclk * (pllmul_bits as u32 + 2)
 }
 
 #[repr(u8)]
@@ -578,26 +1103,74 @@ impl Config {
             1
         };
 
-        let (pllmul_bits, sysclk) = if pllmul == 1 {
+        #[cfg(not(feature = "connectivity"))]
+        let (pllmul_bits, sysclk) = if pllmul <= 1 {
             (None, if let Some(hse) = hse { hse } else { HSI })
         } else {
-            #[cfg(not(feature = "connectivity"))]
-            let pllmul = match pllmul {
-                1..=16 => pllmul,
-                0 => 1,
-                _ => 16,
-            };
-
-            #[cfg(feature = "connectivity")]
-            let pllmul = match pllmul {
-                4..=9 => pllmul,
-                0..=3 => 4,
-                _ => 9,
-            };
+            let pllmul = if pllmul > 16 { 16 } else { pllmul };
 
             (Some(pllmul as u8 - 2), pllsrcclk * pllmul)
         };
 
+        #[cfg(feature = "connectivity")]
+        let (prediv2, pll2mul, pll3mul, pll2clk) = pll23_setup(&cfgr);
+
+        // With HSE, PREDIV1 and PLL2 give more frequencies than the multiplier alone
+        #[cfg(feature = "connectivity")]
+        let (pllmul_bits, sysclk, prediv1, prediv1src) = match (hse, cfgr.sysclk) {
+            (Some(hse), Some(sysclk)) if sysclk != hse => {
+                let (prediv1, prediv1src, pllmul_bits, sysclk) =
+                    prediv1_search(hse, pll2clk, sysclk);
+                (Some(pllmul_bits), sysclk, prediv1, prediv1src)
+            }
+            _ if pllmul <= 1 => (
+                None,
+                if let Some(hse) = hse { hse } else { HSI },
+                1,
+                Prediv1Src::Hse,
+            ),
+            _ => {
+                let pllmul = match pllmul {
+                    4..=9 => pllmul,
+                    0..=3 => 4,
+                    _ => 9,
+                };
+
+                (
+                    Some(pllmul as u8 - 2),
+                    pllsrcclk * pllmul,
+                    1,
+                    Prediv1Src::Hse,
+                )
+            }
+        };
+
+        Self {
+            hse,
+            hse_bypass,
+            pllmul: pllmul_bits,
+            css: cfgr.css,
+            #[cfg(feature = "connectivity")]
+            prediv1,
+            #[cfg(feature = "connectivity")]
+            prediv1src,
+            #[cfg(feature = "connectivity")]
+            prediv2,
+            #[cfg(feature = "connectivity")]
+            pll2mul,
+            #[cfg(feature = "connectivity")]
+            pll3mul,
+            #[cfg(feature = "connectivity")]
+            i2s_pll3: cfgr.i2s_pll3,
+            ..Self::RESET
+        }
+        .with_prescalers(&cfgr, sysclk)
+    }
+
+    /// Sets the bus and peripheral prescalers requested in `cfgr` for `sysclk`
+    ///
+    /// The requested frequencies must not be zero.
+    const fn with_prescalers(self, cfgr: &CFGR, sysclk: u32) -> Self {
         let hpre_bits = if let Some(hclk) = cfgr.hclk {
             match sysclk / hclk {
                 0..=1 => HPre::Div1,
@@ -652,7 +1225,7 @@ impl Config {
 
         // usbpre == false: divide clock by 1.5, otherwise no division
         #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
-        let usbpre = match (hse, pllmul_bits, sysclk) {
+        let usbpre = match (self.hse, self.pllmul, sysclk) {
             (Some(_), Some(_), 72_000_000) => UsbPre::Div1_5,
             _ => UsbPre::Div1,
         };
@@ -669,29 +1242,82 @@ impl Config {
         };
 
         Self {
-            hse,
-            hse_bypass,
-            pllmul: pllmul_bits,
             hpre: hpre_bits,
             ppre1: ppre1_bits,
             ppre2: ppre2_bits,
             #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
             usbpre,
             adcpre: apre_bits,
-            allow_overclock: false,
+            ..self
         }
     }
 
     // NOTE: to maintain the invariant that the existence of a Clocks
     // value implies frozen clocks, this function must not be pub.
     fn get_clocks(&self) -> Clocks {
+        let raw = self.raw_clocks();
+
+        assert!(
+            self.allow_overclock
+                || (raw.sysclk <= 72_000_000
+                    && raw.hclk <= 72_000_000
+                    && raw.pclk1 <= 36_000_000
+                    && raw.pclk2 <= 72_000_000
+                    && raw.adcclk <= 14_000_000)
+        );
+
+        Clocks {
+            hclk: raw.hclk.Hz(),
+            pclk1: raw.pclk1.Hz(),
+            pclk2: raw.pclk2.Hz(),
+            ppre1: raw.ppre1,
+            ppre2: raw.ppre2,
+            sysclk: raw.sysclk.Hz(),
+            adcclk: raw.adcclk.Hz(),
+            #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+            usbclk_valid: raw.usbclk_valid,
+            #[cfg(feature = "connectivity")]
+            pll2clk: raw.pll2clk.map(Hertz::from_raw),
+            #[cfg(feature = "connectivity")]
+            pll3clk: raw.pll3clk.map(Hertz::from_raw),
+            generation: GENERATION.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Computes the frequencies in Hz, without checking them
+    const fn raw_clocks(&self) -> RawClocks {
+        #[cfg(feature = "connectivity")]
+        let (pll2clk, pll3clk) = match self.hse {
+            Some(hse) => {
+                let input = hse / self.prediv2 as u32;
+                (
+                    match self.pll2mul {
+                        Some(mul) => Some(input * mul as u32),
+                        None => None,
+                    },
+                    match self.pll3mul {
+                        Some(mul) => Some(input * mul as u32),
+                        None => None,
+                    },
+                )
+            }
+            None => (None, None),
+        };
+
         let sysclk = if let Some(pllmul_bits) = self.pllmul {
-            let pllsrcclk = if let Some(hse) = self.hse {
-                hse
-            } else {
-                HSI / 2
+            #[cfg(not(feature = "connectivity"))]
+            let pllsrcclk = match self.hse {
+                Some(hse) => hse / (1 + self.pllxtpre as u32),
+                None => HSI / 2,
             };
-            pllsrcclk * (pllmul_bits as u32 + 2)
+            #[cfg(feature = "connectivity")]
+            let pllsrcclk = match (self.hse, self.prediv1src, pll2clk) {
+                (Some(hse), Prediv1Src::Hse, _) => hse / self.prediv1 as u32,
+                (Some(_), Prediv1Src::Pll2, Some(pll2clk)) => pll2clk / self.prediv1 as u32,
+                (Some(_), Prediv1Src::Pll2, None) => panic!("PLL2 clocks PREDIV1 but is disabled"),
+                (None, _, _) => HSI / 2,
+            };
+            pll_output(pllsrcclk, pllmul_bits)
         } else if let Some(hse) = self.hse {
             hse
         } else {
@@ -715,35 +1341,234 @@ impl Config {
 
         // the USB clock is only valid if an external crystal is used, the PLL is enabled, and the
         // PLL output frequency is a supported one.
-        #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
         let usbclk_valid = matches!(
             (self.hse, self.pllmul, sysclk),
             (Some(_), Some(_), 72_000_000) | (Some(_), Some(_), 48_000_000)
         );
 
-        assert!(
-            self.allow_overclock
-                || (sysclk <= 72_000_000
-                    && hclk <= 72_000_000
-                    && pclk1 <= 36_000_000
-                    && pclk2 <= 72_000_000
-                    && adcclk <= 14_000_000)
-        );
-
-        Clocks {
-            hclk: hclk.Hz(),
-            pclk1: pclk1.Hz(),
-            pclk2: pclk2.Hz(),
+        RawClocks {
+            sysclk,
+            hclk,
+            pclk1,
+            pclk2,
+            adcclk,
             ppre1,
             ppre2,
-            sysclk: sysclk.Hz(),
-            adcclk: adcclk.Hz(),
-            #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
             usbclk_valid,
+            #[cfg(feature = "connectivity")]
+            pll2clk,
+            #[cfg(feature = "connectivity")]
+            pll3clk,
+        }
+    }
+}
+
+/// Error of [`CFGR::try_freeze`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockError {
+    /// A requested frequency is zero
+    InvalidFrequency,
+    /// The requested SYSCLK, PLL2 or PLL3 frequency cannot be reached exactly, or SYSCLK is
+    /// above the device maximum
+    PllOutOfRange,
+    /// USB needs 48 MHz, from HSE and the PLL at 48 or 72 MHz, or the device has no USB
+    UsbClock,
+    /// The ADC clock is above the device maximum (14 MHz, 12 MHz on value line)
+    AdcClock,
+    /// APB1 is above the device maximum (36 MHz, 24 MHz on value line and USB access line)
+    Apb1Clock,
+}
+
+/// Maximum frequencies of a device line, in Hz
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockLimits {
+    pub sysclk: u32,
+    pub pclk1: u32,
+    pub adcclk: u32,
+    pub usb: bool,
+}
+
+impl ClockLimits {
+    /// STM32F100
+    pub const VALUE_LINE: Self = Self {
+        sysclk: 24_000_000,
+        pclk1: 24_000_000,
+        adcclk: 12_000_000,
+        usb: false,
+    };
+    /// STM32F101
+    pub const ACCESS_LINE: Self = Self {
+        sysclk: 36_000_000,
+        pclk1: 36_000_000,
+        adcclk: 14_000_000,
+        usb: false,
+    };
+    /// STM32F102
+    pub const USB_ACCESS_LINE: Self = Self {
+        sysclk: 48_000_000,
+        pclk1: 24_000_000,
+        adcclk: 14_000_000,
+        usb: true,
+    };
+    /// STM32F103
+    pub const PERFORMANCE_LINE: Self = Self {
+        sysclk: 72_000_000,
+        pclk1: 36_000_000,
+        adcclk: 14_000_000,
+        usb: true,
+    };
+    /// STM32F105 and STM32F107
+    pub const CONNECTIVITY_LINE: Self = Self::PERFORMANCE_LINE;
+
+    /// Limits of the selected device
+    #[cfg(feature = "stm32f100")]
+    pub const DEVICE: Self = Self::VALUE_LINE;
+    /// Limits of the selected device
+    #[cfg(feature = "stm32f101")]
+    pub const DEVICE: Self = Self::ACCESS_LINE;
+    /// Limits of the selected device
+    #[cfg(feature = "stm32f103")]
+    pub const DEVICE: Self = Self::PERFORMANCE_LINE;
+    /// Limits of the selected device
+    #[cfg(feature = "connectivity")]
+    pub const DEVICE: Self = Self::CONNECTIVITY_LINE;
+}
+
+/// Returns `true` if the frequency is requested as 0 Hz
+const fn is_zero(freq: Option<u32>) -> bool {
+    matches!(freq, Some(0))
+}
+
+impl Config {
+    /// Searches the clock sources, the PLL dividers and multipliers reaching the requested
+    /// frequencies, and checks them against `limits`
+    ///
+    /// SYSCLK, and PLL2 and PLL3 on connectivity line devices, must be reached exactly. The PLL
+    /// is clocked in order of preference by HSE, then HSE / 2 (or PREDIV1 from HSE or PLL2).
+    /// HSI / 2 is only used when no HSE is set, a requested HSE is never replaced by HSI. The bus and ADC prescalers are rounded as with
+    /// [`from_cfgr`](Self::from_cfgr).
+    pub const fn try_from_cfgr(cfgr: CFGR, limits: ClockLimits) -> Result<Self, ClockError> {
+        if is_zero(cfgr.hse)
+            || is_zero(cfgr.sysclk)
+            || is_zero(cfgr.hclk)
+            || is_zero(cfgr.pclk1)
+            || is_zero(cfgr.pclk2)
+            || is_zero(cfgr.adcclk)
+        {
+            return Err(ClockError::InvalidFrequency);
+        }
+        #[cfg(feature = "connectivity")]
+        if is_zero(cfgr.pll2clk) || is_zero(cfgr.pll3clk) {
+            return Err(ClockError::InvalidFrequency);
+        }
+
+        let hse = cfgr.hse;
+        let sysclk = match (cfgr.sysclk, hse) {
+            (Some(sysclk), _) => sysclk,
+            (None, Some(hse)) => hse,
+            (None, None) => HSI,
+        };
+        let direct = match hse {
+            Some(hse) => sysclk == hse,
+            None => sysclk == HSI,
+        };
+
+        let mut config = Self {
+            hse,
+            hse_bypass: cfgr.hse_bypass,
+            css: cfgr.css,
+            ..Self::RESET
+        };
+
+        #[cfg(not(feature = "connectivity"))]
+        if !direct {
+            match pll_search(hse, sysclk) {
+                Some((pllmul_bits, pllxtpre)) => {
+                    config.pllmul = Some(pllmul_bits);
+                    config.pllxtpre = pllxtpre;
+                }
+                None => return Err(ClockError::PllOutOfRange),
+            }
+        }
+
+        #[cfg(feature = "connectivity")]
+        {
+            let (prediv2, pll2mul, pll3mul, pll2clk) = pll23_setup(&cfgr);
+            if !pll23_exact(cfgr.pll2clk, pll2mul, hse, prediv2)
+                || !pll23_exact(cfgr.pll3clk, pll3mul, hse, prediv2)
+            {
+                return Err(ClockError::PllOutOfRange);
+            }
+            config.prediv2 = prediv2;
+            config.pll2mul = pll2mul;
+            config.pll3mul = pll3mul;
+            config.i2s_pll3 = cfgr.i2s_pll3;
+
+            if !direct {
+                match pll_search(hse, pll2clk, sysclk) {
+                    Some((prediv1, prediv1src, pllmul_bits)) => {
+                        config.prediv1 = prediv1;
+                        config.prediv1src = prediv1src;
+                        config.pllmul = Some(pllmul_bits);
+                    }
+                    None => return Err(ClockError::PllOutOfRange),
+                }
+            }
+        }
+
+        let config = config.with_prescalers(&cfgr, sysclk);
+        let raw = config.raw_clocks();
+
+        if raw.sysclk > limits.sysclk || raw.hclk > limits.sysclk {
+            return Err(ClockError::PllOutOfRange);
+        }
+        if cfgr.usb && !(limits.usb && raw.usbclk_valid) {
+            return Err(ClockError::UsbClock);
+        }
+        if raw.adcclk > limits.adcclk {
+            return Err(ClockError::AdcClock);
+        }
+        if raw.pclk1 > limits.pclk1 {
+            return Err(ClockError::Apb1Clock);
+        }
+        Ok(config)
+    }
+}
+
+/// Returns `true` if PLL2 or PLL3 reaches the requested frequency
+#[cfg(feature = "connectivity")]
+const fn pll23_exact(
+    target: Option<u32>,
+    pllmul: Option<u8>,
+    hse: Option<u32>,
+    prediv2: u8,
+) -> bool {
+    match (target, pllmul, hse) {
+        (None, _, _) => true,
+        (Some(target), Some(pllmul), Some(hse)) => {
+            hse % prediv2 as u32 == 0 && hse / prediv2 as u32 * pllmul as u32 == target
         }
+        _ => false,
     }
 }
 
+/// Frequencies in Hz computed from a [`Config`]
+struct RawClocks {
+    sysclk: u32,
+    hclk: u32,
+    pclk1: u32,
+    pclk2: u32,
+    adcclk: u32,
+    ppre1: u8,
+    ppre2: u8,
+    usbclk_valid: bool,
+    #[cfg(feature = "connectivity")]
+    pll2clk: Option<u32>,
+    #[cfg(feature = "connectivity")]
+    pll3clk: Option<u32>,
+}
+
 #[test]
 fn rcc_config_usb() {
     let cfgr = CFGR::default()
@@ -763,6 +1588,21 @@ fn rcc_config_usb() {
         usbpre: UsbPre::Div1,
         adcpre: AdcPre::Div8,
         allow_overclock: false,
+        css: false,
+        #[cfg(not(feature = "connectivity"))]
+        pllxtpre: false,
+        #[cfg(feature = "connectivity")]
+        prediv1: 1,
+        #[cfg(feature = "connectivity")]
+        prediv1src: Prediv1Src::Hse,
+        #[cfg(feature = "connectivity")]
+        prediv2: 1,
+        #[cfg(feature = "connectivity")]
+        pll2mul: None,
+        #[cfg(feature = "connectivity")]
+        pll3mul: None,
+        #[cfg(feature = "connectivity")]
+        i2s_pll3: false,
     };
     assert_eq!(config, config_expected);
 
@@ -777,6 +1617,217 @@ fn rcc_config_usb() {
         adcclk: 6.MHz(),
         #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
         usbclk_valid: true,
+        #[cfg(feature = "connectivity")]
+        pll2clk: None,
+        #[cfg(feature = "connectivity")]
+        pll3clk: None,
+        generation: 0,
     };
     assert_eq!(clocks, clocks_expected);
 }
+
+#[test]
+fn rcc_config_solver() {
+    let limits = ClockLimits::PERFORMANCE_LINE;
+    let sysclk = |cfgr| Config::try_from_cfgr(cfgr, limits).map(|c| c.raw_clocks().sysclk);
+
+    // HSE / 2 * 9, or PREDIV1 = 2
+    let cfgr = CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(36));
+    let config = Config::try_from_cfgr(cfgr, limits).unwrap();
+    assert_eq!(config.raw_clocks().sysclk, 36_000_000);
+    #[cfg(not(feature = "connectivity"))]
+    assert!(config.pllxtpre);
+    #[cfg(feature = "connectivity")]
+    assert_eq!(config.prediv1, 2);
+
+    // Reachable from HSI / 2 * 16 only, but HSE is requested
+    let cfgr = CFGR::new().use_hse(Hertz::MHz(12)).sysclk(Hertz::MHz(64));
+    assert_eq!(sysclk(cfgr), Err(ClockError::PllOutOfRange));
+    let cfgr = CFGR::new().sysclk(Hertz::MHz(36));
+    assert_eq!(sysclk(cfgr), Ok(36_000_000));
+
+    // Below the PLL output range
+    let cfgr = CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(4));
+    assert_eq!(sysclk(cfgr), Err(ClockError::PllOutOfRange));
+    let cfgr = CFGR::new().sysclk(Hertz::MHz(2));
+    assert_eq!(sysclk(cfgr), Err(ClockError::PllOutOfRange));
+
+    // Zero frequencies
+    let cfgr = || CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(72));
+    for cfgr in [
+        cfgr().pclk1(Hertz::from_raw(0)),
+        cfgr().pclk2(Hertz::from_raw(0)),
+        cfgr().hclk(Hertz::from_raw(0)),
+        cfgr().adcclk(Hertz::from_raw(0)),
+        cfgr().sysclk(Hertz::from_raw(0)),
+        cfgr().use_hse(Hertz::from_raw(0)),
+    ] {
+        assert_eq!(sysclk(cfgr), Err(ClockError::InvalidFrequency));
+    }
+
+    // The rounding `from_cfgr` does not underflow the multiplier: it falls back to HSE or HSI
+    // without PLL, or to the lowest PREDIV1 output on connectivity line devices
+    let cfgr = CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(4));
+    #[cfg(not(feature = "connectivity"))]
+    assert_eq!(Config::from_cfgr(cfgr).raw_clocks().sysclk, 8_000_000);
+    #[cfg(feature = "connectivity")]
+    assert_eq!(Config::from_cfgr(cfgr).raw_clocks().sysclk, 20_000_000);
+    let cfgr = CFGR::new().sysclk(Hertz::MHz(2));
+    assert_eq!(Config::from_cfgr(cfgr).raw_clocks().sysclk, 8_000_000);
+}
+
+#[cfg(feature = "connectivity")]
+#[test]
+fn rcc_config_connectivity() {
+    // 25 MHz crystal with the 50 MHz RMII clock from PLL3
+    let cfgr = CFGR::default()
+        .use_hse(25.MHz())
+        .pll2clk(40.MHz())
+        .pll3clk(50.MHz())
+        .sysclk(72.MHz())
+        .pclk1(36.MHz());
+
+    let config = Config::from_cfgr(cfgr);
+    assert_eq!(config.prediv2, 5);
+    assert_eq!(config.pll2mul, Some(8));
+    assert_eq!(config.pll3mul, Some(10));
+    assert_eq!(config.prediv1src, Prediv1Src::Pll2);
+    assert_eq!(config.prediv1, 5);
+    assert_eq!(config.pllmul, Some(7));
+
+    let clocks = config.get_clocks();
+    assert_eq!(clocks.sysclk(), 72.MHz::<1, 1>());
+    assert_eq!(clocks.pclk1(), 36.MHz::<1, 1>());
+    assert_eq!(clocks.pll2clk(), Some(40.MHz()));
+    assert_eq!(clocks.pll3clk(), Some(50.MHz()));
+    assert!(clocks.usbclk_valid());
+
+    // Without PLL2, PREDIV1 divides HSE for the x6.5 multiplier
+    let cfgr = CFGR::default().use_hse(25.MHz()).sysclk(32_500.kHz());
+    let config = Config::from_cfgr(cfgr);
+    assert_eq!(config.prediv1src, Prediv1Src::Hse);
+    assert_eq!(config.prediv1, 5);
+    assert_eq!(config.pllmul, Some(0b1101));
+    assert_eq!(config.pll2mul, None);
+    assert_eq!(config.get_clocks().sysclk(), 32_500.kHz::<1, 1>());
+
+    // Unreachable frequencies are rounded down
+    let cfgr = CFGR::default().use_hse(8.MHz()).sysclk(70.MHz());
+    let config = Config::from_cfgr(cfgr);
+    assert_eq!(config.prediv1, 1);
+    assert_eq!(config.pllmul, Some(6));
+    assert_eq!(config.get_clocks().sysclk(), 64.MHz::<1, 1>());
+}
+
+#[test]
+fn rcc_config_limits() {
+    const HSE_72MHZ: CFGR = CFGR::new()
+        .use_hse(Hertz::MHz(8))
+        .sysclk(Hertz::MHz(72))
+        .pclk1(Hertz::MHz(36));
+
+    // Evaluated at compile time
+    const CONFIG: Result<Config, ClockError> =
+        Config::try_from_cfgr(HSE_72MHZ, ClockLimits::PERFORMANCE_LINE);
+    assert!(CONFIG.is_ok());
+
+    let usb = || CFGR {
+        usb: true,
+        ..HSE_72MHZ
+    };
+    let limits = ClockLimits::PERFORMANCE_LINE;
+    assert!(Config::try_from_cfgr(usb(), limits).is_ok());
+    assert_eq!(
+        Config::try_from_cfgr(usb(), ClockLimits::CONNECTIVITY_LINE).map(|c| c.raw_clocks().sysclk),
+        Ok(72_000_000)
+    );
+    assert_eq!(
+        Config::try_from_cfgr(usb(), ClockLimits::ACCESS_LINE),
+        Err(ClockError::PllOutOfRange)
+    );
+    assert_eq!(
+        Config::try_from_cfgr(usb(), ClockLimits::VALUE_LINE),
+        Err(ClockError::PllOutOfRange)
+    );
+
+    // USB needs HSE
+    let hsi = || CFGR::new().sysclk(Hertz::MHz(36));
+    assert!(Config::try_from_cfgr(hsi(), limits).is_ok());
+    let hsi_usb = CFGR { usb: true, ..hsi() };
+    assert_eq!(
+        Config::try_from_cfgr(hsi_usb, limits),
+        Err(ClockError::UsbClock)
+    );
+
+    // 48 MHz USB access line, APB1 limited to 24 MHz
+    let usb_48mhz = || CFGR {
+        usb: true,
+        ..CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(48))
+    };
+    assert!(Config::try_from_cfgr(usb_48mhz(), ClockLimits::USB_ACCESS_LINE).is_ok());
+    assert_eq!(
+        Config::try_from_cfgr(
+            usb_48mhz().pclk1(Hertz::MHz(48)),
+            ClockLimits::USB_ACCESS_LINE
+        ),
+        Err(ClockError::Apb1Clock)
+    );
+    assert_eq!(
+        Config::try_from_cfgr(usb_48mhz(), ClockLimits::ACCESS_LINE),
+        Err(ClockError::PllOutOfRange)
+    );
+
+    // No USB on access and value lines
+    let hse_24mhz = || CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(24));
+    assert!(Config::try_from_cfgr(hse_24mhz(), ClockLimits::VALUE_LINE).is_ok());
+    assert!(Config::try_from_cfgr(hse_24mhz(), ClockLimits::ACCESS_LINE).is_ok());
+    assert_eq!(
+        Config::try_from_cfgr(
+            CFGR {
+                usb: true,
+                ..hse_24mhz()
+            },
+            ClockLimits::ACCESS_LINE
+        ),
+        Err(ClockError::UsbClock)
+    );
+
+    // 36 MHz from HSI / 2 * 9
+    let hsi_36mhz = || CFGR::new().sysclk(Hertz::MHz(36));
+    assert!(Config::try_from_cfgr(hsi_36mhz(), ClockLimits::ACCESS_LINE).is_ok());
+    assert_eq!(
+        Config::try_from_cfgr(hsi_36mhz(), ClockLimits::VALUE_LINE),
+        Err(ClockError::PllOutOfRange)
+    );
+
+    // Not a multiple of the PLL input
+    let hse_70mhz = CFGR::new().use_hse(Hertz::MHz(8)).sysclk(Hertz::MHz(70));
+    assert_eq!(
+        Config::try_from_cfgr(hse_70mhz, limits),
+        Err(ClockError::PllOutOfRange)
+    );
+
+    // ADC clock above 14 MHz, or 12 MHz on value line
+    let adc = HSE_72MHZ.adcclk(Hertz::MHz(36));
+    assert_eq!(
+        Config::try_from_cfgr(adc, limits),
+        Err(ClockError::AdcClock)
+    );
+    let adc = hse_24mhz().adcclk(Hertz::MHz(12));
+    assert!(Config::try_from_cfgr(adc, ClockLimits::VALUE_LINE).is_ok());
+    let adc = CFGR::new()
+        .use_hse(Hertz::MHz(8))
+        .sysclk(Hertz::MHz(32))
+        .adcclk(Hertz::MHz(16));
+    assert_eq!(
+        Config::try_from_cfgr(adc, ClockLimits::ACCESS_LINE),
+        Err(ClockError::AdcClock)
+    );
+
+    // APB1 above 36 MHz
+    let apb1 = HSE_72MHZ.pclk1(Hertz::MHz(72));
+    assert_eq!(
+        Config::try_from_cfgr(apb1, limits),
+        Err(ClockError::Apb1Clock)
+    );
+}
@@ -0,0 +1,173 @@
+//! Clock security system (CSS)
+//!
+//! When enabled with [`CFGR::enable_css`](super::CFGR::enable_css), a failure of the HSE clock
+//! switches SYSCLK to HSI, stops HSE and the PLL if it runs from HSE, and raises the
+//! non-maskable interrupt. The NMI handler must clear the CSS flag, or it is entered again
+//! immediately:
+//!
+//! ```rust,ignore
+//! // `CLOCKS` holds the frequencies returned by `freeze`
+//! #[exception]
+//! unsafe fn NMI() {
+//!     if let Some(clocks) = rcc::handle_css_failure(&CLOCKS) {
+//!         // Reconfigure the peripherals depending on the clock frequencies
+//!     }
+//! }
+//! ```
+//!
+//! Drivers created before the failure can check [`is_degraded`] to know that the frequencies
+//! they were configured with are no longer valid.
+
+use super::{Clocks, Hertz, GENERATION, HSI};
+use crate::pac::RCC;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static DEGRADED: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` once an HSE failure has been handled by [`handle_css_failure`]
+pub fn is_degraded() -> bool {
+    DEGRADED.load(Ordering::Relaxed)
+}
+
+/// Handles an HSE failure detected by the clock security system, to be called from the `NMI`
+/// handler
+///
+/// Returns `None` if the CSS flag is not set. Otherwise the flag is cleared and, if the PLL
+/// ran from HSE, it is restarted from HSI / 2 with the highest multiplier not exceeding the
+/// previous SYSCLK (64 MHz at most). A PLL clocked by HSI / 2 keeps running at the same
+/// frequency, only the PLL2 and PLL3 outputs of connectivity line devices are lost. `clocks`
+/// are the frequencies frozen before the failure, the bus prescalers are kept and the returned
+/// frequencies are scaled accordingly. The USB clock is no longer valid.
+pub fn handle_css_failure(clocks: &Clocks) -> Option<Clocks> {
+    let rcc = unsafe { &*RCC::ptr() };
+    if rcc.cir().read().cssf().bit_is_clear() {
+        return None;
+    }
+    rcc.cir().write(|w| w.cssc().clear());
+    DEGRADED.store(true, Ordering::Relaxed);
+
+    let cfgr = rcc.cfgr().read();
+    let (sysclk, pllmul) = recovered_sysclk(
+        cfgr.sws().bits(),
+        cfgr.pllsrc().bit_is_set(),
+        clocks.sysclk.raw(),
+    );
+    if let Some(pllmul) = pllmul {
+        #[allow(unused_unsafe)]
+        rcc.cfgr()
+            .modify(|_, w| unsafe { w.pllmul().bits(pllmul as u8 - 2).pllsrc().clear_bit() });
+        rcc.cr().modify(|_, w| w.pllon().set_bit());
+        while rcc.cr().read().pllrdy().bit_is_clear() {}
+
+        #[allow(unused_unsafe)]
+        rcc.cfgr().modify(|_, w| unsafe { w.sw().bits(0b10) });
+        while rcc.cfgr().read().sws().bits() != 0b10 {}
+    }
+
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    Some(Clocks {
+        generation,
+        ..clocks.with_sysclk(sysclk)
+    })
+}
+
+/// Returns SYSCLK after an HSE failure, and the multiplier to restart the PLL from HSI / 2 with
+///
+/// `sws` is the SYSCLK source read after the failure and `sysclk` the frequency before it.
+fn recovered_sysclk(sws: u8, pllsrc_hse: bool, sysclk: u32) -> (u32, Option<u32>) {
+    match (sws, pllsrc_hse) {
+        // The PLL runs from HSI / 2, the hardware leaves it as SYSCLK
+        (0b10, false) => (sysclk, None),
+        // The PLL ran from HSE and was stopped
+        (_, true) => {
+            let pllsrcclk = HSI / 2;
+            #[cfg(not(feature = "connectivity"))]
+            let (min_pllmul, max_pllmul) = (2, 16);
+            #[cfg(feature = "connectivity")]
+            let (min_pllmul, max_pllmul) = (4, 9);
+
+            let pllmul = (sysclk / pllsrcclk).min(max_pllmul);
+            if pllmul >= min_pllmul {
+                (pllsrcclk * pllmul, Some(pllmul))
+            } else {
+                (HSI, None)
+            }
+        }
+        _ => (HSI, None),
+    }
+}
+
+impl Clocks {
+    /// Returns the frequencies with the same prescalers and another SYSCLK
+    fn with_sysclk(&self, sysclk: u32) -> Self {
+        let hpre = self.sysclk.raw() / self.hclk.raw();
+        let hclk = sysclk / hpre;
+        let pclk1 = hclk / self.ppre1 as u32;
+        let pclk2 = hclk / self.ppre2 as u32;
+        let adcpre = self.pclk2.raw() / self.adcclk.raw();
+
+        Self {
+            hclk: Hertz::from_raw(hclk),
+            pclk1: Hertz::from_raw(pclk1),
+            pclk2: Hertz::from_raw(pclk2),
+            sysclk: Hertz::from_raw(sysclk),
+            adcclk: Hertz::from_raw(pclk2 / adcpre),
+            #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+            usbclk_valid: false,
+            // HSE clocks PLL2 and PLL3
+            #[cfg(feature = "connectivity")]
+            pll2clk: None,
+            #[cfg(feature = "connectivity")]
+            pll3clk: None,
+            ..*self
+        }
+    }
+}
+
+#[test]
+fn css_degraded_clocks() {
+    use super::{Config, CFGR};
+    use fugit::RateExtU32;
+
+    let cfgr = CFGR::default()
+        .use_hse(8.MHz())
+        .sysclk(72.MHz())
+        .pclk1(36.MHz())
+        .adcclk(12.MHz());
+    let clocks = Config::from_cfgr(cfgr).get_clocks();
+
+    // PLL from HSI / 2 * 16
+    let degraded = clocks.with_sysclk(64_000_000);
+    assert_eq!(degraded.sysclk(), Hertz::MHz(64));
+    assert_eq!(degraded.hclk(), Hertz::MHz(64));
+    assert_eq!(degraded.pclk1(), Hertz::MHz(32));
+    assert_eq!(degraded.pclk2(), Hertz::MHz(64));
+    assert_eq!(degraded.pclk1_tim(), Hertz::MHz(64));
+    assert_eq!(degraded.adcclk(), Hertz::from_raw(10_666_666));
+}
+
+#[test]
+fn css_recovered_sysclk() {
+    // PLL from HSI / 2, SYSCLK is unchanged
+    assert_eq!(
+        recovered_sysclk(0b10, false, 36_000_000),
+        (36_000_000, None)
+    );
+    // PLL from HSE, SYSCLK switched to HSI by the hardware
+    #[cfg(not(feature = "connectivity"))]
+    assert_eq!(
+        recovered_sysclk(0b00, true, 72_000_000),
+        (64_000_000, Some(16))
+    );
+    #[cfg(feature = "connectivity")]
+    assert_eq!(
+        recovered_sysclk(0b00, true, 72_000_000),
+        (36_000_000, Some(9))
+    );
+    assert_eq!(
+        recovered_sysclk(0b00, true, 24_000_000),
+        (24_000_000, Some(6))
+    );
+    // HSE as SYSCLK
+    assert_eq!(recovered_sysclk(0b00, false, 8_000_000), (HSI, None));
+}
@@ -0,0 +1,136 @@
+//! Low-speed oscillators: LSE and LSI
+//!
+//! LSE is the 32.768 kHz oscillator of the backup domain, used by the RTC. It keeps running
+//! in Stop and Standby modes and on VBAT, and can only be configured with write access to the
+//! backup domain. LSI is the internal RC oscillator of about 40 kHz (30 to 60 kHz), used by the
+//! RTC and the independent watchdog.
+//!
+//! ```rust,ignore
+//! let mut bkp = rcc.bkp.constrain(dp.BKP, &mut dp.PWR);
+//! // LSE may take up to a few seconds to start, give up after about 3 s
+//! if bkp.start_lse(LseMode::Crystal, 3 * clocks.sysclk().raw()).is_err() {
+//!     bkp.disable_lse();
+//!     rcc.csr.start_lsi(clocks.sysclk().raw()).unwrap();
+//! }
+//! ```
+//!
+//! Unlike on other families, the LSE drive capability cannot be adjusted on STM32F1, so
+//! [`LseMode`] only selects between a crystal and an external clock.
+
+use super::CSR;
+use crate::backup_domain::BackupDomain;
+use crate::pac::RCC;
+
+/// Number of SYSCLK cycles between two reads of a ready flag
+const POLL_CYCLES: u32 = 1_000;
+
+/// LSE configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LseMode {
+    /// Crystal or ceramic resonator between OSC32_IN and OSC32_OUT
+    Crystal,
+    /// External clock on OSC32_IN, OSC32_OUT is left floating
+    Bypass,
+}
+
+/// Error of [`BackupDomain::start_lse`] and [`CSR::start_lsi`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OscError {
+    /// The oscillator was not ready within the timeout, it is left enabled
+    Timeout,
+}
+
+/// Turns LSE on, switching it off first if the bypass setting changes
+fn enable_lse(mode: LseMode) {
+    // NOTE(unsafe) only BDCR is accessed, and the caller holds a `&mut BackupDomain`
+    let rcc = unsafe { &*RCC::ptr() };
+    let bypass = mode == LseMode::Bypass;
+    let bdcr = rcc.bdcr().read();
+    if bdcr.lseon().bit_is_set() && bdcr.lsebyp().bit() == bypass {
+        return;
+    }
+    // LSEBYP can only be written while LSE is off
+    rcc.bdcr().modify(|_, w| w.lseon().clear_bit());
+    while rcc.bdcr().read().lserdy().bit_is_set() {}
+    rcc.bdcr().modify(|_, w| w.lsebyp().bit(bypass));
+    rcc.bdcr().modify(|_, w| w.lseon().set_bit());
+}
+
+/// Waits for `ready` for about `timeout` SYSCLK cycles
+fn wait(ready: impl Fn() -> bool, mut timeout: u32) -> Result<(), OscError> {
+    loop {
+        if ready() {
+            return Ok(());
+        }
+        if timeout == 0 {
+            return Err(OscError::Timeout);
+        }
+        let cycles = timeout.min(POLL_CYCLES);
+        cortex_m::asm::delay(cycles);
+        timeout -= cycles;
+    }
+}
+
+impl BackupDomain {
+    /// Starts LSE and waits at most `timeout` SYSCLK cycles for it to be ready
+    ///
+    /// A crystal typically needs up to 2 s. On timeout, e.g. when no crystal is fitted, the
+    /// oscillator keeps running and [`is_lse_ready`](Self::is_lse_ready) can be polled later,
+    /// or [`disable_lse`](Self::disable_lse) called to fall back to LSI.
+    pub fn start_lse(&mut self, mode: LseMode, timeout: u32) -> Result<(), OscError> {
+        self.enable_lse(mode);
+        wait(|| self.is_lse_ready(), timeout)
+    }
+
+    /// Turns LSE on without waiting for it to be ready
+    pub fn enable_lse(&mut self, mode: LseMode) {
+        enable_lse(mode);
+    }
+
+    /// Turns LSE off
+    ///
+    /// The RTC stops if it runs from LSE.
+    pub fn disable_lse(&mut self) {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.bdcr().modify(|_, w| w.lseon().clear_bit());
+    }
+
+    /// Returns `true` if LSE is stable
+    pub fn is_lse_ready(&self) -> bool {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.bdcr().read().lserdy().bit_is_set()
+    }
+}
+
+impl CSR {
+    /// Starts LSI and waits at most `timeout` SYSCLK cycles for it to be ready
+    ///
+    /// LSI typically starts within 100 µs.
+    pub fn start_lsi(&mut self, timeout: u32) -> Result<(), OscError> {
+        self.enable_lsi();
+        wait(|| self.is_lsi_ready(), timeout)
+    }
+
+    /// Turns LSI on without waiting for it to be ready
+    pub fn enable_lsi(&mut self) {
+        // NOTE(unsafe) only the LSI bits of CSR are accessed, and `self` owns them
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.csr().modify(|_, w| w.lsion().set_bit());
+    }
+
+    /// Turns LSI off
+    ///
+    /// The RTC stops if it runs from LSI. A running independent watchdog keeps LSI on.
+    pub fn disable_lsi(&mut self) {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.csr().modify(|_, w| w.lsion().clear_bit());
+    }
+
+    /// Returns `true` if LSI is stable
+    pub fn is_lsi_ready(&self) -> bool {
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.csr().read().lsirdy().bit_is_set()
+    }
+}
@@ -0,0 +1,97 @@
+//! Microcontroller clock output (MCO) on PA8
+//!
+//! ```rust,ignore
+//! let mut gpioa = dp.GPIOA.split();
+//! let mut mco = Mco::new(McoSource::Sysclk, gpioa.pa8, &mut gpioa.crh);
+//! mco.set_source(McoSource::Hse);
+//! ```
+//!
+//! The pin is limited to 50 MHz, so SYSCLK at 72 MHz cannot be output as is.
+
+use crate::gpio::{self, Active, Alternate, IOPinSpeed, OutputSpeed, PushPull, PA8};
+use crate::pac::RCC;
+
+/// Clock driven on the MCO pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum McoSource {
+    /// No clock, the pin stays low
+    None,
+    /// System clock
+    Sysclk,
+    /// Internal 8 MHz RC oscillator
+    Hsi,
+    /// External oscillator
+    Hse,
+    /// PLL output divided by 2
+    PllDiv2,
+    /// PLL2 output
+    #[cfg(feature = "connectivity")]
+    Pll2,
+    /// PLL3 output divided by 2
+    #[cfg(feature = "connectivity")]
+    Pll3Div2,
+    /// XT1 external 3-25 MHz oscillator, e.g. for an Ethernet PHY
+    #[cfg(feature = "connectivity")]
+    Xt1,
+    /// PLL3 output, e.g. 50 MHz for the Ethernet RMII clock
+    #[cfg(feature = "connectivity")]
+    Pll3,
+}
+
+impl McoSource {
+    const fn bits(self) -> u8 {
+        match self {
+            Self::None => 0b0000,
+            Self::Sysclk => 0b0100,
+            Self::Hsi => 0b0101,
+            Self::Hse => 0b0110,
+            Self::PllDiv2 => 0b0111,
+            #[cfg(feature = "connectivity")]
+            Self::Pll2 => 0b1000,
+            #[cfg(feature = "connectivity")]
+            Self::Pll3Div2 => 0b1001,
+            #[cfg(feature = "connectivity")]
+            Self::Xt1 => 0b1010,
+            #[cfg(feature = "connectivity")]
+            Self::Pll3 => 0b1011,
+        }
+    }
+}
+
+/// Clock output on PA8
+///
+/// Owning the pin gives exclusive access to the MCO bits of `RCC_CFGR`.
+pub struct Mco {
+    pin: PA8<Alternate<PushPull>>,
+}
+
+impl Mco {
+    /// Outputs a clock on PA8, configured as alternate function push-pull at 50 MHz
+    ///
+    /// The selected clock must already be running.
+    pub fn new<MODE: Active>(
+        source: McoSource,
+        pin: PA8<MODE>,
+        cr: &mut gpio::Cr<'A', true>,
+    ) -> Self {
+        let mut pin = pin.into_alternate_push_pull(cr);
+        pin.set_speed(cr, IOPinSpeed::Mhz50);
+        let mut mco = Self { pin };
+        mco.set_source(source);
+        mco
+    }
+
+    /// Changes the clock driven on the MCO pin
+    pub fn set_source(&mut self, source: McoSource) {
+        // NOTE(unsafe) only the MCO bits are modified, and `self` owns the MCO pin
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cfgr()
+            .modify(|_, w| unsafe { w.mco().bits(source.bits()) });
+    }
+
+    /// Stops the clock output and releases the pin
+    pub fn release(mut self) -> PA8<Alternate<PushPull>> {
+        self.set_source(McoSource::None);
+        self.pin
+    }
+}
@@ -4,11 +4,21 @@
 use crate::pac::{RCC, RTC};
 
 use crate::backup_domain::BackupDomain;
+use crate::rcc::{LseMode, OscError};
 use crate::time::{Hertz, Hz};
 
 use core::convert::Infallible;
 use core::marker::PhantomData;
 
+mod calendar;
+pub use calendar::*;
+mod calibration;
+pub use calibration::*;
+#[cfg(any(feature = "rtic", feature = "rtic2"))]
+mod monotonic;
+#[cfg(any(feature = "rtic", feature = "rtic2"))]
+pub use monotonic::*;
+
 // The LSE runs at at 32 768 hertz unless an external clock is provided
 const LSE_HERTZ: Hertz = Hz(32_768);
 const LSI_HERTZ: Hertz = Hz(40_000);
@@ -44,6 +54,8 @@ pub enum RestoredOrNewRtc<CS> {
 pub struct Rtc<CS = RtcClkLse> {
     regs: RTC,
     _clock_source: PhantomData<CS>,
+    /// Unix time at which the counter was 0
+    epoch: u32,
 }
 
 impl Rtc<RtcClkLse> {
@@ -53,6 +65,10 @@ impl Rtc<RtcClkLse> {
 
       The frequency is set to 1 Hz.
 
+      LSE is started for a crystal, unless it already runs, e.g. with an external clock started
+      by [`BackupDomain::start_lse`]. Panics if LSE is not ready after `u32::MAX` SYSCLK
+      cycles, see [`try_new`](Self::try_new) to handle a missing crystal.
+
       Since the RTC is part of the backup domain, The RTC counter is not reset by normal resets or
       power cycles where (VBAT) still has power. Use [set_time](#method.set_time) if you want to
       reset the counter.
@@ -63,13 +79,32 @@ impl Rtc<RtcClkLse> {
       [`restore_or_new`](Rtc::<RtcClkLse>::restore_or_new) instead.
     */
     pub fn new(regs: RTC, bkp: &mut BackupDomain) -> Self {
+        match Self::try_new(regs, bkp, u32::MAX) {
+            Ok(rtc) => rtc,
+            Err(_) => panic!("LSE is not ready"),
+        }
+    }
+
+    /// Initialises the RTC like [`new`](Self::new), waiting at most `timeout` SYSCLK cycles
+    /// for LSE
+    ///
+    /// On timeout, the RTC peripheral is given back, e.g. to fall back to
+    /// [`new_lsi`](Rtc::<RtcClkLsi>::new_lsi).
+    pub fn try_new(
+        regs: RTC,
+        bkp: &mut BackupDomain,
+        timeout: u32,
+    ) -> Result<Self, (RTC, OscError)> {
+        if let Err(e) = Self::enable_rtc(bkp, timeout) {
+            return Err((regs, e));
+        }
+
         let mut result = Rtc {
             regs,
             _clock_source: PhantomData,
+            epoch: 0,
         };
 
-        Self::enable_rtc(bkp);
-
         // Set the prescaler to make it count up once every second.
         let prl = LSE_HERTZ.raw() - 1;
         assert!(prl < 1 << 20);
@@ -80,7 +115,7 @@ impl Rtc<RtcClkLse> {
                 .write(|w| unsafe { w.bits(prl as u16 as u32) });
         });
 
-        result
+        Ok(result)
     }
 
     /// Tries to obtain currently running RTC to prevent a reset in case it was running from VBAT.
@@ -104,6 +139,7 @@ impl Rtc<RtcClkLse> {
             RestoredOrNewRtc::Restored(Rtc {
                 regs,
                 _clock_source: PhantomData,
+                epoch: 0,
             })
         }
     }
@@ -116,18 +152,25 @@ impl Rtc<RtcClkLse> {
     }
 
     /// Enables the RTC device with the lse as the clock
-    fn enable_rtc(_bkp: &mut BackupDomain) {
+    fn enable_rtc(bkp: &mut BackupDomain, timeout: u32) -> Result<(), OscError> {
         // NOTE: Safe RCC access because we are only accessing bdcr
         // and we have a &mut on BackupDomain
         let rcc = unsafe { &*RCC::ptr() };
+        // Keep the mode of an LSE started with `BackupDomain::start_lse`
+        let bdcr = rcc.bdcr().read();
+        let mode = if bdcr.lseon().bit_is_set() && bdcr.lsebyp().bit_is_set() {
+            LseMode::Bypass
+        } else {
+            LseMode::Crystal
+        };
+        bkp.start_lse(mode, timeout)?;
         rcc.bdcr().modify(|_, w| {
-            // start the LSE oscillator
-            w.lseon().set_bit();
             // Enable the RTC
             w.rtcen().set_bit();
             // Set the source of the RTC to LSE
             w.rtcsel().lse()
         });
+        Ok(())
     }
 }
 
@@ -151,6 +194,7 @@ impl Rtc<RtcClkLsi> {
         let mut result = Rtc {
             regs,
             _clock_source: PhantomData,
+            epoch: 0,
         };
 
         Self::enable_rtc(bkp);
@@ -177,6 +221,7 @@ impl Rtc<RtcClkLsi> {
             RestoredOrNewRtc::Restored(Rtc {
                 regs,
                 _clock_source: PhantomData,
+                epoch: 0,
             })
         }
     }
@@ -226,6 +271,7 @@ impl Rtc<RtcClkHseDiv128> {
         let mut result = Rtc {
             regs,
             _clock_source: PhantomData,
+            epoch: 0,
         };
 
         Self::enable_rtc(bkp);
@@ -256,6 +302,7 @@ impl Rtc<RtcClkHseDiv128> {
             RestoredOrNewRtc::Restored(Rtc {
                 regs,
                 _clock_source: PhantomData,
+                epoch: 0,
             })
         }
     }
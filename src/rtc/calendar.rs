@@ -0,0 +1,278 @@
+//! Calendar on top of the RTC counter
+//!
+//! The counter is interpreted as seconds (1 Hz RTC frequency) elapsed since an epoch, which is
+//! the Unix epoch (1970-01-01 00:00:00) by default. Moving the epoch, e.g. with
+//! [`Rtc::set_epoch`], allows setting the date without writing the counter or going past year
+//! 2106, and the epoch can be kept in two backup data registers across resets.
+//!
+//! ```rust,ignore
+//! let mut rtc = Rtc::new(dp.RTC, &mut backup_domain);
+//! rtc.set_datetime(&DateTime::new(2024, 2, 29, 12, 0, 0).unwrap());
+//!
+//! let now = rtc.datetime();
+//! rtc.set_alarm_datetime(&DateTime::new(2024, 3, 1, 7, 30, 0).unwrap());
+//! ```
+
+use super::Rtc;
+use crate::backup_domain::BackupDomain;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Calendar date and time (UTC, proleptic Gregorian calendar)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// Year, from 1970
+    pub year: u16,
+    /// Month, 1 to 12
+    pub month: u8,
+    /// Day of the month, 1 to 31
+    pub day: u8,
+    /// Hour, 0 to 23
+    pub hour: u8,
+    /// Minute, 0 to 59
+    pub minute: u8,
+    /// Second, 0 to 59
+    pub second: u8,
+}
+
+/// Day of the week
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// Returns `true` for years with a February 29th
+pub const fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Returns the number of days in `month` (1 to 12) of `year`
+pub const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    /// Creates a date and time, returns `None` if it does not exist or is before 1970
+    pub const fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<Self> {
+        if year < 1970
+            || month < 1
+            || month > 12
+            || day < 1
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return None;
+        }
+        Some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Converts seconds since the Unix epoch to a date and time
+    pub const fn from_unix(secs: u64) -> Self {
+        let days = secs / SECONDS_PER_DAY;
+        let rem = secs % SECONDS_PER_DAY;
+
+        // Civil from days, with years starting on March 1st so that the leap day is last
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z % 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+
+    /// Converts the date and time to seconds since the Unix epoch
+    pub const fn to_unix(&self) -> u64 {
+        // Days from civil
+        let year = self.year as u64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year / 400;
+        let yoe = year % 400;
+        let month = self.month as u64;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as u64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        days * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    /// Returns the day of the week
+    pub const fn weekday(&self) -> Weekday {
+        // 1970-01-01 was a Thursday
+        match (self.to_unix() / SECONDS_PER_DAY + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+impl<CS> Rtc<CS> {
+    /// Sets the Unix time at which the counter was 0
+    ///
+    /// The counter keeps its value, so this shifts the date returned by
+    /// [`datetime`](Self::datetime).
+    pub fn set_epoch(&mut self, epoch: u32) {
+        self.epoch = epoch;
+    }
+
+    /// Returns the Unix time at which the counter was 0
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Stores the epoch in the backup data registers `register` and `register + 1`
+    /// (zero based index of DR1 to DR10)
+    pub fn save_epoch(&self, bkp: &mut BackupDomain, register: usize) {
+        bkp.write_data_register_low(register, self.epoch as u16);
+        bkp.write_data_register_low(register + 1, (self.epoch >> 16) as u16);
+    }
+
+    /// Restores the epoch stored by [`save_epoch`](Self::save_epoch)
+    pub fn load_epoch(&mut self, bkp: &BackupDomain, register: usize) {
+        self.epoch = bkp.read_data_register_low(register) as u32
+            | (bkp.read_data_register_low(register + 1) as u32) << 16;
+    }
+
+    /// Sets the counter to the current date and time
+    ///
+    /// Panics if `datetime` is before the epoch or more than `u32::MAX` seconds after it.
+    pub fn set_datetime(&mut self, datetime: &DateTime) {
+        let counter = self.counter_at(datetime);
+        self.set_time(counter);
+    }
+
+    /// Sets the date and time by moving the epoch, without writing the counter
+    ///
+    /// Panics if the resulting epoch is before 1970 or after 2106.
+    pub fn adjust_datetime(&mut self, datetime: &DateTime) {
+        let epoch = datetime.to_unix() - self.current_time() as u64;
+        self.epoch = u32::try_from(epoch).unwrap();
+    }
+
+    /// Returns the current date and time
+    pub fn datetime(&self) -> DateTime {
+        DateTime::from_unix(self.epoch as u64 + self.current_time() as u64)
+    }
+
+    /// Sets the alarm to a date and time, see [`set_alarm`](Self::set_alarm)
+    ///
+    /// Panics if `datetime` is before the epoch or more than `u32::MAX` seconds after it.
+    pub fn set_alarm_datetime(&mut self, datetime: &DateTime) {
+        let counter = self.counter_at(datetime);
+        self.set_alarm(counter);
+    }
+
+    fn counter_at(&self, datetime: &DateTime) -> u32 {
+        let secs = datetime.to_unix() - self.epoch as u64;
+        u32::try_from(secs).unwrap()
+    }
+}
+
+#[test]
+fn datetime_unix_epoch() {
+    let epoch = DateTime::new(1970, 1, 1, 0, 0, 0).unwrap();
+    assert_eq!(epoch.to_unix(), 0);
+    assert_eq!(DateTime::from_unix(0), epoch);
+    assert_eq!(epoch.weekday(), Weekday::Thursday);
+
+    let last = DateTime::new(2106, 2, 7, 6, 28, 15).unwrap();
+    assert_eq!(last.to_unix(), u32::MAX as u64);
+    assert_eq!(DateTime::from_unix(u32::MAX as u64), last);
+}
+
+#[test]
+fn datetime_leap_years() {
+    assert!(is_leap_year(2000));
+    assert!(is_leap_year(2024));
+    assert!(!is_leap_year(1900));
+    assert!(!is_leap_year(2100));
+    assert!(!is_leap_year(2023));
+
+    assert!(DateTime::new(2024, 2, 29, 0, 0, 0).is_some());
+    assert!(DateTime::new(2000, 2, 29, 0, 0, 0).is_some());
+    assert!(DateTime::new(2023, 2, 29, 0, 0, 0).is_none());
+    assert!(DateTime::new(2100, 2, 29, 0, 0, 0).is_none());
+
+    let leap_day = DateTime::new(2024, 2, 29, 23, 59, 59).unwrap();
+    assert_eq!(leap_day.to_unix(), 1_709_251_199);
+    assert_eq!(
+        DateTime::from_unix(1_709_251_200),
+        DateTime::new(2024, 3, 1, 0, 0, 0).unwrap()
+    );
+    assert_eq!(leap_day.weekday(), Weekday::Thursday);
+
+    // 2100 is not a leap year, February 28th is followed by March 1st
+    let secs = DateTime::new(2100, 2, 28, 23, 59, 59).unwrap().to_unix();
+    assert_eq!(
+        DateTime::from_unix(secs + 1),
+        DateTime::new(2100, 3, 1, 0, 0, 0).unwrap()
+    );
+}
+
+#[test]
+fn datetime_roundtrip() {
+    let dt = DateTime::new(2000, 1, 1, 0, 0, 0).unwrap();
+    assert_eq!(dt.to_unix(), 946_684_800);
+    assert_eq!(dt.weekday(), Weekday::Saturday);
+
+    let dt = DateTime::new(2038, 1, 19, 3, 14, 8).unwrap();
+    assert_eq!(dt.to_unix(), 1 << 31);
+
+    // Every day from 1970 to 2200 at a changing time of day
+    let mut secs = 0;
+    while secs < 7_258_118_400 {
+        let dt = DateTime::from_unix(secs);
+        assert_eq!(
+            DateTime::new(dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second),
+            Some(dt)
+        );
+        assert_eq!(dt.to_unix(), secs);
+        secs += SECONDS_PER_DAY + 3_607;
+    }
+}
@@ -0,0 +1,96 @@
+//! RTC calibration and output on the TAMPER pin
+//!
+//! The RTC can be slowed down by up to 121 ppm, in steps of 0.954 ppm, by ignoring `CAL` clock
+//! pulses every 2^20 RTC clock cycles. A crystal running too fast can be trimmed this way; one
+//! running too slow needs a prescaler reduced by one and a larger calibration value.
+//!
+//! To measure the crystal, route the RTC clock divided by 64 (512 Hz from LSE) to PC13 with
+//! [`RtcOutput::ClockDiv64`] and measure it with an accurate reference, or let
+//! [`Rtc::measure_ppm`] compare the counter with the core clock when it runs from a crystal.
+//! The clock output is not affected by the calibration, the alarm and second pulses are.
+//!
+//! ```rust,ignore
+//! let mut rtc = Rtc::new(dp.RTC, &mut backup_domain);
+//! let timer = MonoTimer::new(cp.DWT, cp.DCB, &clocks);
+//! let ppm = rtc.measure_ppm(timer, 1.Hz(), 10);
+//! if ppm > 0 {
+//!     rtc.set_calibration(&mut backup_domain, ppm as u32);
+//! }
+//! ```
+
+use super::Rtc;
+use crate::backup_domain::BackupDomain;
+use crate::time::{Hertz, MonoTimer};
+
+/// Maximum correction of [`Rtc::set_calibration`] in ppm
+pub const MAX_CALIBRATION_PPM: u32 = 121;
+
+/// Signal driven on the TAMPER pin (PC13)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtcOutput {
+    /// The pin is free for GPIO or tamper detection
+    None,
+    /// RTC clock divided by 64, for calibration measurements
+    ClockDiv64,
+    /// Pulse on alarm
+    Alarm,
+    /// Pulse every second (each counter increment)
+    Second,
+}
+
+impl<CS> Rtc<CS> {
+    /// Slows the RTC down by `ppm` parts per million, rounded to the nearest step
+    ///
+    /// The value is kept in the backup domain. Panics if `ppm` is above
+    /// [`MAX_CALIBRATION_PPM`].
+    pub fn set_calibration(&mut self, bkp: &mut BackupDomain, ppm: u32) {
+        assert!(ppm <= MAX_CALIBRATION_PPM);
+        // CAL pulses are masked every 2^20 clock cycles
+        let cal = ((ppm << 20) + 500_000) / 1_000_000;
+        bkp._regs
+            .rtccr()
+            .modify(|_, w| unsafe { w.cal().bits(cal.min(0x7f) as u8) });
+    }
+
+    /// Returns the calibration set by [`set_calibration`](Self::set_calibration), in ppm
+    pub fn calibration(&self, bkp: &BackupDomain) -> u32 {
+        let cal = bkp._regs.rtccr().read().cal().bits() as u32;
+        (cal * 1_000_000 + (1 << 19)) >> 20
+    }
+
+    /// Selects the signal driven on the TAMPER pin (PC13)
+    ///
+    /// Any output takes over PC13, whatever its GPIO configuration, and cannot be used together
+    /// with tamper detection.
+    pub fn output(&mut self, bkp: &mut BackupDomain, output: RtcOutput) {
+        bkp._regs.rtccr().modify(|_, w| {
+            w.cco().bit(output == RtcOutput::ClockDiv64);
+            w.asoe()
+                .bit(matches!(output, RtcOutput::Alarm | RtcOutput::Second));
+            w.asos().bit(output == RtcOutput::Second)
+        });
+    }
+
+    /// Measures the deviation of the RTC counter against the core clock, in ppm
+    ///
+    /// `frequency` is the nominal counter frequency (1 Hz unless changed with
+    /// [`select_frequency`](Self::select_frequency)). Blocks for `ticks` counter increments,
+    /// which must last less than 2^32 core clock cycles (59 s at 72 MHz). A positive value
+    /// means the RTC is fast and can be corrected with
+    /// [`set_calibration`](Self::set_calibration), if the core clock runs from a crystal.
+    pub fn measure_ppm(&self, timer: MonoTimer, frequency: Hertz, ticks: u32) -> i32 {
+        assert!(ticks > 0);
+        let expected = timer.frequency().raw() as u64 * ticks as u64 / frequency.raw() as u64;
+        assert!(expected <= u32::MAX as u64);
+
+        // Synchronize with a counter increment
+        let start = self.current_time();
+        while self.current_time() == start {}
+        let instant = timer.now();
+        let end = start.wrapping_add(1).wrapping_add(ticks);
+        while self.current_time() != end {}
+        let measured = instant.elapsed() as u64;
+
+        ((expected as i64 - measured as i64) * 1_000_000 / measured as i64) as i32
+    }
+}
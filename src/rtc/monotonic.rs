@@ -0,0 +1,273 @@
+//! RTC based Monotonic implementations
+//!
+//! The RTC keeps counting from LSE or LSI in Stop mode, and its alarm wakes the device up through
+//! EXTI line 17 (`RTCALARM` interrupt). This makes it a time base for applications sleeping in
+//! Stop mode between events, at the cost of a coarse resolution (e.g. 1024 Hz from LSE) and
+//! slow alarm writes (a few RTC clock cycles).
+//!
+//! The 32-bit counter wraps around after `2^32 / FREQ` seconds, which is handled by the
+//! wrapping comparison of the instants.
+//!
+//! The counter registers are synchronized to the APB1 clock after a wakeup from Stop mode only
+//! once the `RSF` flag is set again. This is done in the alarm interrupt; if the device is woken
+//! up by another source, call `resync` before reading the time.
+
+use super::{Rtc, RtcClkLse, RtcClkLsi, LSE_HERTZ, LSI_HERTZ};
+use crate::pac::{self, EXTI, RTC};
+
+/// RTC clock source available in Stop mode
+pub trait LowPowerClock: crate::Sealed {
+    /// RTC clock frequency in Hz
+    const HZ: u32;
+}
+
+impl crate::Sealed for RtcClkLse {}
+impl LowPowerClock for RtcClkLse {
+    const HZ: u32 = LSE_HERTZ.raw();
+}
+
+impl crate::Sealed for RtcClkLsi {}
+impl LowPowerClock for RtcClkLsi {
+    const HZ: u32 = LSI_HERTZ.raw();
+}
+
+/// Sets the counter frequency to `FREQ`, resets the counter and routes the alarm to EXTI line 17
+fn init<CS: LowPowerClock, const FREQ: u32>(rtc: &mut Rtc<CS>, exti: &mut EXTI) {
+    // The manual says that the zero value for the prescaler is not recommended
+    assert!(CS::HZ % FREQ == 0 && CS::HZ / FREQ >= 2);
+    let prl = CS::HZ / FREQ - 1;
+    rtc.perform_write(|s| {
+        s.regs.prlh().write(|w| unsafe { w.bits(prl >> 16) });
+        s.regs
+            .prll()
+            .write(|w| unsafe { w.bits(prl as u16 as u32) });
+    });
+    rtc.set_time(0);
+    rtc.unlisten_seconds();
+
+    // The RTC alarm is connected to the rising edge of EXTI line 17
+    exti.rtsr().modify(|_, w| w.tr17().set_bit());
+    exti.imr().modify(|_, w| w.mr17().set_bit());
+    exti.pr().write(|w| w.pr17().clear_bit_by_one());
+}
+
+fn regs() -> &'static pac::rtc::RegisterBlock {
+    // NOTE(unsafe) the monotonic owns the RTC
+    unsafe { &*RTC::ptr() }
+}
+
+fn resync() {
+    let rtc = regs();
+    rtc.crl().modify(|_, w| w.rsf().clear_bit());
+    while rtc.crl().read().rsf().bit_is_clear() {}
+}
+
+fn counter() -> u32 {
+    let rtc = regs();
+    while rtc.crl().read().rsf().bit_is_clear() {}
+    loop {
+        // The two halves are not latched together
+        let high = rtc.cnth().read().bits();
+        let low = rtc.cntl().read().bits();
+        if rtc.cnth().read().bits() == high {
+            return (high << 16) | low;
+        }
+    }
+}
+
+fn set_alarm(at: u32) {
+    let rtc = regs();
+    // The alarm flag is set when the counter goes from ALR to ALR + 1
+    let alarm = at.wrapping_sub(1);
+
+    while rtc.crl().read().rtoff().bit_is_clear() {}
+    rtc.crl().modify(|_, w| w.cnf().set_bit());
+    // TODO: Remove this `allow` once these fields are made safe for stm32f100
+    #[allow(unused_unsafe)]
+    {
+        rtc.alrh()
+            .write(|w| unsafe { w.alrh().bits((alarm >> 16) as u16) });
+        rtc.alrl().write(|w| unsafe { w.alrl().bits(alarm as u16) });
+    }
+    rtc.crl().modify(|_, w| w.cnf().clear_bit());
+    while rtc.crl().read().rtoff().bit_is_clear() {}
+}
+
+fn clear_alarm_flag() {
+    regs().crl().modify(|_, w| w.alrf().clear_bit());
+    // NOTE(unsafe) write 1 to clear of the line 17 pending bit only
+    unsafe { (*EXTI::ptr()).pr().write(|w| w.pr17().clear_bit_by_one()) };
+}
+
+fn listen_alarm(b: bool) {
+    let rtc = regs();
+    while rtc.crl().read().rtoff().bit_is_clear() {}
+    rtc.crl().modify(|_, w| w.cnf().set_bit());
+    rtc.crh().modify(|_, w| w.alrie().bit(b));
+    rtc.crl().modify(|_, w| w.cnf().clear_bit());
+    while rtc.crl().read().rtoff().bit_is_clear() {}
+}
+
+#[cfg(feature = "rtic")]
+pub use self::rtic1::*;
+
+#[cfg(feature = "rtic")]
+mod rtic1 {
+    use super::*;
+    use rtic_monotonic::Monotonic;
+
+    /// RTIC Monotonic running on the RTC at `FREQ` Hz, to be bound to `RTCALARM`
+    pub struct RtcMonotonic<CS, const FREQ: u32> {
+        rtc: Rtc<CS>,
+    }
+
+    impl<CS: LowPowerClock> Rtc<CS> {
+        /// Turns the RTC into an RTIC Monotonic counting at `FREQ` Hz
+        ///
+        /// `FREQ` must divide the RTC clock, e.g. 1024 Hz for LSE or 1000 Hz for LSI.
+        /// The counter is reset.
+        pub fn monotonic<const FREQ: u32>(mut self, exti: &mut EXTI) -> RtcMonotonic<CS, FREQ> {
+            init::<CS, FREQ>(&mut self, exti);
+            RtcMonotonic { rtc: self }
+        }
+    }
+
+    impl<CS, const FREQ: u32> RtcMonotonic<CS, FREQ> {
+        /// Waits for the counter registers to be synchronized after a wakeup from Stop mode
+        pub fn resync(&mut self) {
+            resync();
+        }
+
+        /// Releases the RTC
+        pub fn release(mut self) -> Rtc<CS> {
+            self.rtc.unlisten_alarm();
+            self.rtc
+        }
+    }
+
+    impl<CS, const FREQ: u32> Monotonic for RtcMonotonic<CS, FREQ> {
+        type Instant = fugit::TimerInstantU32<FREQ>;
+        type Duration = fugit::TimerDurationU32<FREQ>;
+
+        unsafe fn reset(&mut self) {
+            listen_alarm(true);
+        }
+
+        fn now(&mut self) -> Self::Instant {
+            Self::Instant::from_ticks(counter())
+        }
+
+        fn set_compare(&mut self, instant: Self::Instant) {
+            set_alarm(instant.ticks());
+        }
+
+        fn clear_compare_flag(&mut self) {
+            clear_alarm_flag();
+        }
+
+        fn on_interrupt(&mut self) {
+            resync();
+        }
+
+        #[inline(always)]
+        fn zero() -> Self::Instant {
+            Self::Instant::from_ticks(0)
+        }
+    }
+}
+
+#[cfg(feature = "rtic2")]
+pub use self::rtic2::*;
+
+#[cfg(feature = "rtic2")]
+mod rtic2 {
+    use super::*;
+    use cortex_m::peripheral::NVIC;
+    use rtic_time::monotonic::TimerQueueBasedMonotonic;
+    use rtic_time::timer_queue::{TimerQueue, TimerQueueBackend};
+
+    #[cfg(not(feature = "stm32f101"))]
+    const IRQ: pac::Interrupt = pac::Interrupt::RTCALARM;
+    #[cfg(feature = "stm32f101")]
+    const IRQ: pac::Interrupt = pac::Interrupt::RTC_ALARM;
+
+    static RTC_QUEUE: TimerQueue<RtcBackend> = TimerQueue::new();
+
+    /// Timer queue backend of the RTC
+    pub struct RtcBackend;
+
+    /// RTIC 2 monotonic running on the RTC at `FREQ` Hz
+    ///
+    /// The `RTCALARM` interrupt handler must call [`RtcMono::on_interrupt`].
+    pub struct RtcMono<const FREQ: u32>;
+
+    impl<const FREQ: u32> RtcMono<FREQ> {
+        /// Starts the monotonic
+        ///
+        /// `FREQ` must divide the RTC clock, e.g. 1024 Hz for LSE or 1000 Hz for LSI.
+        /// The counter is reset and the `RTCALARM` interrupt is unmasked in the NVIC.
+        pub fn start<CS: LowPowerClock>(mut rtc: Rtc<CS>, exti: &mut EXTI) {
+            init::<CS, FREQ>(&mut rtc, exti);
+            clear_alarm_flag();
+            RTC_QUEUE.initialize(RtcBackend);
+            // Safety: the interrupt handler only touches the monotonic state
+            unsafe { NVIC::unmask(IRQ) };
+        }
+
+        /// Waits for the counter registers to be synchronized after a wakeup from Stop mode
+        pub fn resync() {
+            resync();
+        }
+
+        /// Handles the `RTCALARM` interrupt
+        ///
+        /// # Safety
+        ///
+        /// Must only be called from the `RTCALARM` interrupt handler.
+        pub unsafe fn on_interrupt() {
+            RTC_QUEUE.on_monotonic_interrupt();
+        }
+    }
+
+    impl TimerQueueBackend for RtcBackend {
+        type Ticks = u32;
+
+        fn now() -> Self::Ticks {
+            counter()
+        }
+
+        fn set_compare(instant: Self::Ticks) {
+            set_alarm(instant);
+        }
+
+        fn clear_compare_flag() {
+            clear_alarm_flag();
+        }
+
+        fn pend_interrupt() {
+            NVIC::pend(IRQ);
+        }
+
+        fn on_interrupt() {
+            resync();
+        }
+
+        fn enable_timer() {
+            listen_alarm(true);
+        }
+
+        fn disable_timer() {
+            listen_alarm(false);
+        }
+
+        fn timer_queue() -> &'static TimerQueue<Self> {
+            &RTC_QUEUE
+        }
+    }
+
+    impl<const FREQ: u32> TimerQueueBasedMonotonic for RtcMono<FREQ> {
+        type Backend = RtcBackend;
+        type Instant = fugit::Instant<u32, 1, FREQ>;
+        type Duration = fugit::Duration<u32, 1, FREQ>;
+    }
+}
@@ -62,15 +62,32 @@ use crate::time::Hertz;
 pub mod monotonic;
 #[cfg(feature = "rtic")]
 pub use monotonic::*;
+#[cfg(feature = "rtic2")]
+pub mod monotonic2;
+#[cfg(feature = "rtic2")]
+pub use monotonic2::*;
+#[cfg(feature = "time-driver")]
+pub mod time_driver;
+#[cfg(feature = "time-driver")]
+pub use time_driver::*;
 pub(crate) mod pins;
+pub mod pulse_counter;
 pub mod pwm_input;
 pub use pins::*;
 pub mod delay;
 pub use delay::*;
+#[cfg(feature = "async")]
+pub mod delay_async;
+#[cfg(feature = "async")]
+pub use delay_async::*;
 pub mod counter;
 pub use counter::*;
 pub mod pwm;
 pub use pwm::*;
+pub mod pwm_dma;
+pub use pwm_dma::*;
+pub mod sync;
+pub use sync::*;
 
 mod hal_02;
 mod hal_1;
@@ -259,7 +276,7 @@ pub enum Ocm {
 }
 
 mod sealed {
-    use super::{Channel, Event, Ocm, DBG};
+    use super::{Channel, Event, Ocm, SlaveMode, TriggerSource, DBG};
     pub trait General {
         type Width: Into<u32> + From<u16>;
         fn max_auto_reload() -> u32;
@@ -292,12 +309,28 @@ mod sealed {
         fn enable_channel(channel: u8, b: bool);
     }
 
+    pub trait WithDma: WithPwm {
+        fn ccr_address(channel: u8) -> u32;
+        fn dmar_address() -> u32;
+        fn enable_update_dma(b: bool);
+        fn set_dma_burst(channel: u8, len: u8);
+    }
+
     pub trait MasterTimer: General {
         type Mms;
+        /// Counter enable signal (`CEN`) is used as trigger output (`TRGO`)
+        const MMS_ENABLE: Self::Mms;
+        /// Update event is used as trigger output (`TRGO`)
+        const MMS_UPDATE: Self::Mms;
         fn master_mode(&mut self, mode: Self::Mms);
     }
+
+    pub trait SlaveTimer: General {
+        fn set_slave_mode(&mut self, trigger: TriggerSource, mode: SlaveMode);
+        fn enable_master_slave_mode(&mut self, b: bool);
+    }
 }
-pub(crate) use sealed::{General, MasterTimer, WithPwm};
+pub(crate) use sealed::{General, MasterTimer, SlaveTimer, WithDma, WithPwm};
 
 pub trait Instance:
     crate::Sealed + rcc::Enable + rcc::Reset + rcc::BusTimerClock + General
@@ -413,10 +446,12 @@ macro_rules! hal {
                     dbg.cr().modify(|_, w| w.$dbg_timX_stop().bit(state));
                 }
             }
-            $(with_pwm!($TIM: $cnum $(, $aoe)?);)?
+            $(with_pwm!($TIM: $cnum $(, $aoe)?); with_dma!($TIM: $cnum);)?
 
             $(impl MasterTimer for $TIM {
                 type Mms = pac::$timbase::cr2::MMS;
+                const MMS_ENABLE: Self::Mms = pac::$timbase::cr2::MMS::Enable;
+                const MMS_UPDATE: Self::Mms = pac::$timbase::cr2::MMS::Update;
                 fn master_mode(&mut self, mode: Self::Mms) {
                     self.cr2().modify(|_,w| w.mms().variant(mode));
                 }
@@ -425,6 +460,38 @@ macro_rules! hal {
     }
 }
 
+macro_rules! with_dma {
+    ($TIM:ty: $cnum:ident) => {
+        impl WithDma for $TIM {
+            #[inline(always)]
+            fn ccr_address(channel: u8) -> u32 {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                tim.ccr(channel as usize).as_ptr() as u32
+            }
+
+            #[inline(always)]
+            fn dmar_address() -> u32 {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                tim.dmar().as_ptr() as u32
+            }
+
+            #[inline(always)]
+            fn enable_update_dma(b: bool) {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                tim.dier().modify(|_, w| w.ude().bit(b));
+            }
+
+            #[inline(always)]
+            fn set_dma_burst(channel: u8, len: u8) {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                // DMA base address is counted in words from CR1, CCR1 is at offset 0x34
+                tim.dcr()
+                    .write(|w| unsafe { w.dba().bits(0x34 / 4 + channel).dbl().bits(len - 1) });
+            }
+        }
+    };
+}
+
 macro_rules! with_pwm {
     ($TIM:ty: CH1) => {
         impl WithPwm for $TIM {
@@ -0,0 +1,328 @@
+//! Asynchronous delays
+//!
+//! The timer runs in one-pulse mode (or SysTick counts down once) and its interrupt wakes the
+//! waiting task, so nothing is busy-waiting. The interrupt handler of the timer must forward
+//! to `on_interrupt`:
+//!
+//! ```rust,ignore
+//! let mut delay = dp.TIM2.delay_async_us(&clocks);
+//! delay.delay_ms(10).await;
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     DelayAsyncUs::<pac::TIM2>::on_interrupt();
+//! }
+//!
+//! #[exception]
+//! fn SysTick() {
+//!     SysDelayAsync::on_interrupt();
+//! }
+//! ```
+
+// Async traits need Rust 1.75, so the crate MSRV does not apply to this module
+#![allow(clippy::incompatible_msrv)]
+
+use super::{Event, FTimer, Instance, Timer};
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::ops::{Deref, DerefMut};
+use core::task::{Poll, Waker};
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::SYST;
+use embedded_hal_async::delay::DelayNs;
+use fugit::{ExtU32Ceil, MicrosDurationU32, TimerDurationU32};
+
+use crate::pac;
+use crate::rcc::Clocks;
+use crate::time::Hertz;
+
+type WakerCell = Mutex<RefCell<Option<Waker>>>;
+
+mod sealed {
+    pub trait WithWaker {
+        fn waker() -> &'static super::WakerCell;
+        unsafe fn steal() -> Self;
+    }
+}
+pub(crate) use sealed::WithWaker;
+
+fn register(cell: &WakerCell, waker: &Waker) {
+    interrupt::free(|cs| {
+        let mut slot = cell.borrow(cs).borrow_mut();
+        match slot.as_ref() {
+            Some(w) if w.will_wake(waker) => {}
+            _ => *slot = Some(waker.clone()),
+        }
+    });
+}
+
+fn wake(cell: &WakerCell) {
+    if let Some(waker) = interrupt::free(|cs| cell.borrow(cs).borrow_mut().take()) {
+        waker.wake();
+    }
+}
+
+/// Timer as an asynchronous delay provider
+pub struct DelayAsync<TIM, const FREQ: u32>(FTimer<TIM, FREQ>);
+
+impl<T, const FREQ: u32> Deref for DelayAsync<T, FREQ> {
+    type Target = FTimer<T, FREQ>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T, const FREQ: u32> DerefMut for DelayAsync<T, FREQ> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// `DelayAsync` with precision of 1 μs (1 MHz sampling)
+pub type DelayAsyncUs<TIM> = DelayAsync<TIM, 1_000_000>;
+
+pub trait DelayAsyncExt: Sized {
+    fn delay_async<const FREQ: u32>(self, clocks: &Clocks) -> DelayAsync<Self, FREQ>;
+    fn delay_async_us(self, clocks: &Clocks) -> DelayAsyncUs<Self> {
+        self.delay_async::<1_000_000>(clocks)
+    }
+}
+
+impl<TIM: Instance + WithWaker> DelayAsyncExt for TIM {
+    fn delay_async<const FREQ: u32>(self, clocks: &Clocks) -> DelayAsync<Self, FREQ> {
+        FTimer::new(self, clocks).delay_async()
+    }
+}
+
+impl<TIM: Instance + WithWaker, const FREQ: u32> FTimer<TIM, FREQ> {
+    pub fn delay_async(self) -> DelayAsync<TIM, FREQ> {
+        DelayAsync(self)
+    }
+}
+
+impl<TIM: Instance + WithWaker, const FREQ: u32> DelayAsync<TIM, FREQ> {
+    /// Waits for given time
+    pub async fn delay(&mut self, time: TimerDurationU32<FREQ>) {
+        let mut ticks = time.ticks().max(1) - 1;
+        while ticks != 0 {
+            let reload = ticks.min(TIM::max_auto_reload());
+
+            unsafe {
+                self.tim.set_auto_reload_unchecked(reload);
+            }
+            self.tim.trigger_update();
+            self.tim.clear_interrupt_flag(Event::Update);
+            self.tim.listen_interrupt(Event::Update, true);
+            // The counter stops and clears the CEN bit at the next update event
+            self.tim.start_one_pulse();
+
+            ticks -= reload;
+
+            poll_fn(|cx| {
+                register(TIM::waker(), cx.waker());
+                if self.tim.is_counter_enabled() {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
+        }
+    }
+
+    /// Handles the timer interrupt, to be called from the interrupt handler of `TIM`
+    pub fn on_interrupt() {
+        // NOTE(unsafe) only the update interrupt enable and flag are touched
+        let mut tim = unsafe { TIM::steal() };
+        tim.listen_interrupt(Event::Update, false);
+        tim.clear_interrupt_flag(Event::Update);
+        wake(TIM::waker());
+    }
+
+    pub fn max_delay(&self) -> TimerDurationU32<FREQ> {
+        TimerDurationU32::from_ticks(TIM::max_auto_reload())
+    }
+
+    /// Releases the TIM peripheral
+    pub fn release(mut self) -> FTimer<TIM, FREQ> {
+        // stop counter
+        self.tim.listen_interrupt(Event::Update, false);
+        self.tim.cr1_reset();
+        self.0
+    }
+}
+
+impl<TIM: Instance + WithWaker, const FREQ: u32> DelayNs for DelayAsync<TIM, FREQ> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay(ns.nanos_at_least()).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        self.delay(us.micros_at_least()).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.delay(ms.millis_at_least()).await
+    }
+}
+
+static SYST_WAKER: WakerCell = Mutex::new(RefCell::new(None));
+
+/// SysTick as an asynchronous delay provider
+pub struct SysDelayAsync(Timer<SYST>);
+
+impl Deref for SysDelayAsync {
+    type Target = Timer<SYST>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for SysDelayAsync {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Timer<SYST> {
+    pub fn delay_async(self) -> SysDelayAsync {
+        SysDelayAsync(self)
+    }
+}
+
+impl SysDelayAsync {
+    /// Waits for given time
+    pub async fn delay(&mut self, us: MicrosDurationU32) {
+        // The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
+        const MAX_RVR: u32 = 0x00FF_FFFF;
+
+        // u64 as a u32 overflows after about 59 s at 72 MHz
+        let mut total_rvr = systick_cycles(us, self.clk);
+
+        while total_rvr != 0 {
+            let current_rvr = total_rvr.min(MAX_RVR as u64) as u32;
+
+            self.tim.set_reload(current_rvr);
+            self.tim.clear_current();
+            self.tim.enable_interrupt();
+            self.tim.enable_counter();
+
+            total_rvr -= current_rvr as u64;
+
+            poll_fn(|cx| {
+                register(&SYST_WAKER, cx.waker());
+                if self.tim.is_counter_enabled() {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
+        }
+    }
+
+    /// Handles the SysTick exception, to be called from the `SysTick` handler
+    pub fn on_interrupt() {
+        // NOTE(unsafe) the counter and interrupt enable bits are only used by this delay
+        unsafe { (*SYST::PTR).csr.modify(|csr| csr & !0b11) };
+        wake(&SYST_WAKER);
+    }
+
+    /// Releases the timer resource
+    pub fn release(mut self) -> Timer<SYST> {
+        self.tim.disable_interrupt();
+        self.tim.disable_counter();
+        self.0
+    }
+}
+
+/// Number of SysTick cycles in `us`
+fn systick_cycles(us: MicrosDurationU32, clk: Hertz) -> u64 {
+    us.ticks() as u64 * (clk.raw() / 1_000_000) as u64
+}
+
+impl DelayNs for SysDelayAsync {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay(ns.nanos_at_least()).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.delay(ms.millis_at_least()).await
+    }
+}
+
+macro_rules! waker {
+    ($($TIM:ty: $WAKER:ident,)+) => {
+        $(
+            static $WAKER: WakerCell = Mutex::new(RefCell::new(None));
+
+            impl WithWaker for $TIM {
+                fn waker() -> &'static WakerCell {
+                    &$WAKER
+                }
+                unsafe fn steal() -> Self {
+                    <$TIM>::steal()
+                }
+            }
+        )+
+    };
+}
+
+waker! {
+    pac::TIM2: TIM2_WAKER,
+    pac::TIM3: TIM3_WAKER,
+}
+
+#[cfg(any(feature = "stm32f100", feature = "stm32f103", feature = "connectivity"))]
+waker! {
+    pac::TIM1: TIM1_WAKER,
+}
+
+#[cfg(feature = "medium")]
+waker! {
+    pac::TIM4: TIM4_WAKER,
+}
+
+#[cfg(any(feature = "high", feature = "connectivity"))]
+waker! {
+    pac::TIM5: TIM5_WAKER,
+}
+
+#[cfg(any(feature = "stm32f100", feature = "high", feature = "connectivity"))]
+waker! {
+    pac::TIM6: TIM6_WAKER,
+}
+
+#[cfg(any(
+    all(feature = "high", any(feature = "stm32f101", feature = "stm32f103")),
+    any(feature = "stm32f100", feature = "connectivity")
+))]
+waker! {
+    pac::TIM7: TIM7_WAKER,
+}
+
+#[cfg(all(feature = "stm32f103", feature = "high"))]
+waker! {
+    pac::TIM8: TIM8_WAKER,
+}
+
+#[cfg(feature = "stm32f100")]
+waker! {
+    pac::TIM15: TIM15_WAKER,
+    pac::TIM16: TIM16_WAKER,
+    pac::TIM17: TIM17_WAKER,
+}
+
+#[test]
+fn systick_cycles_no_overflow() {
+    let clk = Hertz::MHz(72);
+    assert_eq!(systick_cycles(MicrosDurationU32::from_ticks(1), clk), 72);
+    assert_eq!(
+        systick_cycles(MicrosDurationU32::from_ticks(60_000_000), clk),
+        4_320_000_000
+    );
+    assert_eq!(
+        systick_cycles(MicrosDurationU32::from_ticks(u32::MAX), clk),
+        u32::MAX as u64 * 72
+    );
+}
@@ -21,7 +21,7 @@ impl DelayNs for SysDelay {
 
 impl<TIM: Instance, const FREQ: u32> DelayNs for Delay<TIM, FREQ> {
     fn delay_ns(&mut self, ns: u32) {
-        self.delay(ns.micros_at_least());
+        self.delay(ns.nanos_at_least());
     }
 
     fn delay_us(&mut self, us: u32) {
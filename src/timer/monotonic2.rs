@@ -0,0 +1,283 @@
+//! RTIC 2 Monotonic implementation
+//!
+//! The 16-bit timers are extended to 64 bits with a half-period counter: the `C1` compare event
+//! fires in the middle of the period and the update event at its end, so the time can always be
+//! computed without race conditions (see `rtic_time::half_period_counter`). The `C2` compare
+//! channel schedules the timer queue.
+//!
+//! SysTick counts interrupts at the configured tick rate into a 32-bit counter instead, so it
+//! keeps the CPU busier and has a coarser resolution, but needs no timer peripheral.
+//!
+//! The interrupt handler of the timer must forward to `on_interrupt`:
+//!
+//! ```rust,ignore
+//! type Mono = TimMono<pac::TIM2, 1_000_000>;
+//!
+//! Mono::start(dp.TIM2, &clocks);
+//! Mono::delay(10.millis()).await;
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     unsafe { Mono::on_interrupt() };
+//! }
+//! ```
+
+// Async traits need Rust 1.75, so the crate MSRV does not apply to this module
+#![allow(clippy::incompatible_msrv)]
+
+use super::{Event, FTimer, Instance, WithPwm};
+use crate::pac;
+use crate::rcc::Clocks;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::{NVIC, SCB, SYST};
+use rtic_time::half_period_counter::calculate_now;
+use rtic_time::monotonic::TimerQueueBasedMonotonic;
+use rtic_time::timer_queue::{TimerQueue, TimerQueueBackend};
+
+pub use rtic_time::Monotonic;
+
+mod sealed {
+    use super::{AtomicU32, TimBackend, TimerQueue};
+
+    pub trait WithMono: super::Instance + super::WithPwm + Sized + 'static {
+        const IRQ: super::pac::Interrupt;
+        fn half_periods() -> &'static AtomicU32;
+        fn queue() -> &'static TimerQueue<TimBackend<Self>>;
+        unsafe fn steal() -> Self;
+    }
+}
+pub(crate) use sealed::WithMono;
+
+/// Timer queue backend of a general purpose timer
+pub struct TimBackend<TIM>(PhantomData<TIM>);
+
+/// RTIC 2 monotonic running on `TIM` at `FREQ` Hz
+pub struct TimMono<TIM, const FREQ: u32>(PhantomData<TIM>);
+
+/// `TimMono` with precision of 1 μs (1 MHz sampling)
+pub type TimMonoUs<TIM> = TimMono<TIM, 1_000_000>;
+
+impl<TIM: WithMono, const FREQ: u32> TimMono<TIM, FREQ> {
+    /// Starts the monotonic
+    ///
+    /// The timer clock must be a multiple of `FREQ` and `FREQ` must be at least 1/65536 of it.
+    /// The timer interrupt is unmasked in the NVIC, its priority is left to the application.
+    pub fn start(tim: TIM, clocks: &Clocks) {
+        let FTimer { mut tim } = FTimer::<TIM, FREQ>::new(tim, clocks);
+
+        // Safety: u16::MAX is always a valid auto-reload value
+        unsafe { tim.set_auto_reload_unchecked(u16::MAX as u32) };
+        // Loads the prescaler
+        tim.trigger_update();
+        tim.reset_counter();
+        TIM::set_cc_value(0, 0x8000);
+        tim.clear_interrupt_flag(Event::all());
+        TIM::half_periods().store(0, Ordering::SeqCst);
+        tim.listen_interrupt(Event::Update | Event::C1, true);
+
+        TIM::queue().initialize(TimBackend::<TIM>(PhantomData));
+
+        // Safety: the interrupt handler only touches the monotonic state
+        unsafe { NVIC::unmask(TIM::IRQ) };
+        tim.enable_counter();
+    }
+
+    /// Handles the timer interrupt, to be called from the interrupt handler of `TIM`
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the interrupt handler of `TIM`.
+    pub unsafe fn on_interrupt() {
+        TIM::queue().on_monotonic_interrupt();
+    }
+}
+
+impl<TIM: WithMono> TimerQueueBackend for TimBackend<TIM> {
+    type Ticks = u64;
+
+    fn now() -> Self::Ticks {
+        calculate_now(
+            || TIM::half_periods().load(Ordering::Relaxed),
+            // NOTE(unsafe) atomic read with no side effects
+            || {
+                let cnt: u32 = unsafe { TIM::steal() }.read_count().into();
+                cnt as u16
+            },
+        )
+    }
+
+    fn set_compare(instant: Self::Ticks) {
+        let now = Self::now();
+
+        // Only the low 16 bits are compared; farther targets are reached through the
+        // half-period interrupts, which make the queue check again
+        let val = if instant.wrapping_sub(now) <= u16::MAX as u64 {
+            instant as u16
+        } else {
+            0
+        };
+        TIM::set_cc_value(1, val as u32);
+    }
+
+    fn clear_compare_flag() {
+        // NOTE(unsafe) only the `C2` flag is cleared
+        unsafe { TIM::steal() }.clear_interrupt_flag(Event::C2);
+    }
+
+    fn pend_interrupt() {
+        NVIC::pend(TIM::IRQ);
+    }
+
+    fn on_interrupt() {
+        // NOTE(unsafe) only the update and `C1` flags are touched
+        let mut tim = unsafe { TIM::steal() };
+        let flags = tim.get_interrupt_flag();
+        if flags.contains(Event::Update) {
+            tim.clear_interrupt_flag(Event::Update);
+            let prev = TIM::half_periods().fetch_add(1, Ordering::Relaxed);
+            assert!(prev % 2 == 1, "Monotonic must have missed an interrupt!");
+        }
+        if flags.contains(Event::C1) {
+            tim.clear_interrupt_flag(Event::C1);
+            let prev = TIM::half_periods().fetch_add(1, Ordering::Relaxed);
+            assert!(prev % 2 == 0, "Monotonic must have missed an interrupt!");
+        }
+    }
+
+    fn enable_timer() {
+        // NOTE(unsafe) only the `C2` interrupt enable is touched
+        unsafe { TIM::steal() }.listen_interrupt(Event::C2, true);
+    }
+
+    fn disable_timer() {
+        // NOTE(unsafe) only the `C2` interrupt enable is touched
+        unsafe { TIM::steal() }.listen_interrupt(Event::C2, false);
+    }
+
+    fn timer_queue() -> &'static TimerQueue<Self> {
+        TIM::queue()
+    }
+}
+
+impl<TIM: WithMono, const FREQ: u32> TimerQueueBasedMonotonic for TimMono<TIM, FREQ> {
+    type Backend = TimBackend<TIM>;
+    type Instant = fugit::Instant<u64, 1, FREQ>;
+    type Duration = fugit::Duration<u64, 1, FREQ>;
+}
+
+static SYST_TICKS: AtomicU32 = AtomicU32::new(0);
+static SYST_QUEUE: TimerQueue<SysTickBackend> = TimerQueue::new();
+
+/// Timer queue backend of SysTick
+pub struct SysTickBackend;
+
+/// RTIC 2 monotonic running on SysTick with a tick rate of `FREQ` Hz
+pub struct SysTickMono<const FREQ: u32>;
+
+impl<const FREQ: u32> SysTickMono<FREQ> {
+    /// Starts the monotonic
+    ///
+    /// `HCLK` must be a multiple of `FREQ`.
+    pub fn start(mut syst: SYST, clocks: &Clocks) {
+        let hclk = clocks.hclk().raw();
+        assert!(hclk % FREQ == 0);
+        let reload = hclk / FREQ - 1;
+        // The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
+        assert!(reload > 0 && reload <= 0x00FF_FFFF);
+
+        syst.disable_counter();
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(reload);
+        syst.clear_current();
+        SYST_TICKS.store(0, Ordering::SeqCst);
+
+        SYST_QUEUE.initialize(SysTickBackend);
+
+        syst.enable_interrupt();
+        syst.enable_counter();
+    }
+
+    /// Handles the SysTick exception, to be called from the `SysTick` handler
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the `SysTick` handler.
+    pub unsafe fn on_interrupt() {
+        SYST_QUEUE.on_monotonic_interrupt();
+    }
+}
+
+impl TimerQueueBackend for SysTickBackend {
+    type Ticks = u32;
+
+    fn now() -> Self::Ticks {
+        SYST_TICKS.load(Ordering::Relaxed)
+    }
+
+    fn set_compare(_instant: Self::Ticks) {
+        // The queue is checked on every tick
+    }
+
+    fn clear_compare_flag() {}
+
+    fn pend_interrupt() {
+        SCB::set_pendst();
+    }
+
+    fn on_interrupt() {
+        // NOTE(unsafe) reading CSR clears the COUNTFLAG bit, which is only used here
+        if unsafe { (*SYST::PTR).csr.read() } & (1 << 16) != 0 {
+            SYST_TICKS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn timer_queue() -> &'static TimerQueue<Self> {
+        &SYST_QUEUE
+    }
+}
+
+impl<const FREQ: u32> TimerQueueBasedMonotonic for SysTickMono<FREQ> {
+    type Backend = SysTickBackend;
+    type Instant = fugit::Instant<u32, 1, FREQ>;
+    type Duration = fugit::Duration<u32, 1, FREQ>;
+}
+
+macro_rules! mono {
+    ($($TIM:ty: $IRQ:ident, $HALF:ident, $QUEUE:ident,)+) => {
+        $(
+            static $HALF: AtomicU32 = AtomicU32::new(0);
+            static $QUEUE: TimerQueue<TimBackend<$TIM>> = TimerQueue::new();
+
+            impl WithMono for $TIM {
+                const IRQ: pac::Interrupt = pac::Interrupt::$IRQ;
+                fn half_periods() -> &'static AtomicU32 {
+                    &$HALF
+                }
+                fn queue() -> &'static TimerQueue<TimBackend<Self>> {
+                    &$QUEUE
+                }
+                unsafe fn steal() -> Self {
+                    <$TIM>::steal()
+                }
+            }
+        )+
+    };
+}
+
+// Advanced timers have separate update and compare interrupts and are not supported
+mono! {
+    pac::TIM2: TIM2, TIM2_HALF_PERIODS, TIM2_QUEUE,
+    pac::TIM3: TIM3, TIM3_HALF_PERIODS, TIM3_QUEUE,
+}
+
+#[cfg(feature = "medium")]
+mono! {
+    pac::TIM4: TIM4, TIM4_HALF_PERIODS, TIM4_QUEUE,
+}
+
+#[cfg(any(feature = "high", feature = "connectivity"))]
+mono! {
+    pac::TIM5: TIM5, TIM5_HALF_PERIODS, TIM5_QUEUE,
+}
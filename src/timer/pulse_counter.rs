@@ -0,0 +1,352 @@
+//! Counting of external pulses
+//!
+//! The timer counter is clocked by an external signal instead of the internal timer clock.
+//! Two sources are supported:
+//!
+//! - the `ETR` pin in external clock mode 2, with a prescaler (up to /8), digital filter and
+//!   selectable polarity
+//! - the `CH1` (`TI1FP1`) or `CH2` (`TI2FP2`) pin in external clock mode 1, with digital filter
+//!   and selectable polarity
+//!
+//! The 16-bit hardware counter is extended to 32 bits by counting overflows. Call
+//! [`PulseCounter::count`] at least once every 65536 pulses, or listen for
+//! [`Event::Update`](super::Event::Update) and call [`PulseCounter::on_interrupt`] from the timer
+//! interrupt handler.
+//!
+//! The fourth capture/compare channel is used internally (without output) to raise an interrupt
+//! when the count reaches a threshold, see [`PulseCounter::listen_threshold`].
+//!
+//! ```rust,ignore
+//! let gpioa = dp.GPIOA.split();
+//! // TIM2 ETR is on PA0
+//! let mut counter = Timer::new(dp.TIM2, &clocks).pulse_counter_etr(gpioa.pa0, EtrConfig::default());
+//!
+//! counter.listen_threshold(1000).unwrap();
+//! // in TIM2 interrupt
+//! if counter.on_interrupt() {
+//!     // 1000 pulses have been counted
+//! }
+//! ```
+
+use crate::afio::{RInto, Rmp, TimC, TimEtr};
+use crate::pac;
+use crate::timer::{Event, General, Ocm, Timer};
+
+/// Active edge of the external signal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+impl Default for Edge {
+    fn default() -> Self {
+        Self::Rising
+    }
+}
+
+/// Division of the `ETR` signal frequency before it is sampled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EtrPrescaler {
+    Div1 = 0,
+    Div2 = 1,
+    Div4 = 2,
+    Div8 = 3,
+}
+
+impl Default for EtrPrescaler {
+    fn default() -> Self {
+        Self::Div1
+    }
+}
+
+/// External clock mode 2 (`ETR` pin) configuration
+///
+/// The `ETR` frequency after prescaling must stay below a quarter of the timer clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EtrConfig {
+    /// Counted edge
+    pub edge: Edge,
+    /// `ETR` prescaler
+    pub prescaler: EtrPrescaler,
+    /// Digital filter (`ETF`, 0 to 15), see the reference manual for the sampling frequencies
+    pub filter: u8,
+}
+
+/// External clock mode 1 (`TI1`/`TI2` pin) configuration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TiConfig {
+    /// Counted edge
+    pub edge: Edge,
+    /// Digital input filter (`ICxF`, 0 to 15), see the reference manual for the sampling frequencies
+    pub filter: u8,
+}
+
+/// Error of [`PulseCounter::listen_threshold`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The count has already reached the threshold, the interrupt would never be raised
+    ThresholdReached,
+}
+
+/// Returns an error if `count` has already reached `threshold`
+fn check_threshold(count: u32, threshold: u32) -> Result<(), Error> {
+    if threshold <= count {
+        Err(Error::ThresholdReached)
+    } else {
+        Ok(())
+    }
+}
+
+/// Timer counting pulses of an external signal
+pub struct PulseCounter<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+    ovf: u32,
+    threshold: Option<u32>,
+}
+
+impl<TIM: General, PIN> PulseCounter<TIM, PIN> {
+    fn _new(mut tim: TIM, pin: PIN) -> Self {
+        tim.set_prescaler(0);
+        // Safety: u16::MAX is always a valid auto-reload value
+        unsafe { tim.set_auto_reload_unchecked(u16::MAX as u32) };
+        tim.trigger_update();
+        tim.reset_counter();
+        tim.clear_interrupt_flag(Event::all());
+        tim.enable_counter();
+        Self {
+            tim,
+            pin,
+            ovf: 0,
+            threshold: None,
+        }
+    }
+
+    /// Returns the number of pulses counted since the counter was created or reset
+    ///
+    /// Wraps around after `u32::MAX` pulses.
+    pub fn count(&mut self) -> u32 {
+        self.handle_overflow();
+        let cnt: u32 = self.tim.read_count().into();
+        if self.tim.get_interrupt_flag().contains(Event::Update) {
+            // The counter has overflowed after being read
+            self.handle_overflow();
+            let cnt: u32 = self.tim.read_count().into();
+            return self.ovf.wrapping_add(cnt);
+        }
+        self.ovf.wrapping_add(cnt)
+    }
+
+    /// Sets the count to zero
+    pub fn reset(&mut self) {
+        self.tim.reset_counter();
+        self.tim.clear_interrupt_flag(Event::Update);
+        self.ovf = 0;
+    }
+
+    /// Starts listening for an `event`
+    pub fn listen(&mut self, event: Event) {
+        self.tim.listen_interrupt(event, true);
+    }
+
+    /// Stops listening for an `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.tim.listen_interrupt(event, false);
+    }
+
+    /// Stops the counter and releases the timer and the input pin
+    pub fn release(mut self) -> (TIM, PIN) {
+        self.tim.listen_interrupt(Event::all(), false);
+        self.tim.cr1_reset();
+        (self.tim, self.pin)
+    }
+
+    fn handle_overflow(&mut self) {
+        if self.tim.get_interrupt_flag().contains(Event::Update) {
+            self.tim.clear_interrupt_flag(Event::Update);
+            self.ovf = self.ovf.wrapping_add(0x1_0000);
+        }
+    }
+}
+
+macro_rules! hal {
+    ($TIM:ty: $etrX:ident, $tiX:ident) => {
+        impl Timer<$TIM> {
+            /// Counts pulses on the `ETR` pin (external clock mode 2)
+            pub fn pulse_counter_etr(
+                self,
+                pin: impl RInto<<$TIM as TimEtr>::Etr, 0>,
+                config: EtrConfig,
+            ) -> PulseCounter<$TIM, <$TIM as TimEtr>::Etr> {
+                $etrX(self.tim, pin, config)
+            }
+            /// Counts pulses on the `CH1` pin (external clock mode 1 with `TI1FP1`)
+            pub fn pulse_counter_ti1(
+                self,
+                pin: impl RInto<<$TIM as TimC<0>>::In, 0>,
+                config: TiConfig,
+            ) -> PulseCounter<$TIM, <$TIM as TimC<0>>::In> {
+                $tiX(self.tim, pin.rinto(), false, config)
+            }
+            /// Counts pulses on the `CH2` pin (external clock mode 1 with `TI2FP2`)
+            pub fn pulse_counter_ti2(
+                self,
+                pin: impl RInto<<$TIM as TimC<1>>::In, 0>,
+                config: TiConfig,
+            ) -> PulseCounter<$TIM, <$TIM as TimC<1>>::In> {
+                $tiX(self.tim, pin.rinto(), true, config)
+            }
+        }
+
+        impl<const R: u8> Rmp<$TIM, R> {
+            /// Counts pulses on the `ETR` pin (external clock mode 2)
+            pub fn pulse_counter_etr(
+                self,
+                pin: impl RInto<<$TIM as TimEtr>::Etr, R>,
+                config: EtrConfig,
+            ) -> PulseCounter<$TIM, <$TIM as TimEtr>::Etr> {
+                $etrX(self.0, pin, config)
+            }
+            /// Counts pulses on the `CH1` pin (external clock mode 1 with `TI1FP1`)
+            pub fn pulse_counter_ti1(
+                self,
+                pin: impl RInto<<$TIM as TimC<0>>::In, R>,
+                config: TiConfig,
+            ) -> PulseCounter<$TIM, <$TIM as TimC<0>>::In> {
+                $tiX(self.0, pin.rinto(), false, config)
+            }
+            /// Counts pulses on the `CH2` pin (external clock mode 1 with `TI2FP2`)
+            pub fn pulse_counter_ti2(
+                self,
+                pin: impl RInto<<$TIM as TimC<1>>::In, R>,
+                config: TiConfig,
+            ) -> PulseCounter<$TIM, <$TIM as TimC<1>>::In> {
+                $tiX(self.0, pin.rinto(), true, config)
+            }
+        }
+
+        fn $etrX<const R: u8>(
+            mut tim: $TIM,
+            pin: impl RInto<<$TIM as TimEtr>::Etr, R>,
+            config: EtrConfig,
+        ) -> PulseCounter<$TIM, <$TIM as TimEtr>::Etr> {
+            let pin = pin.rinto();
+            tim.cr1_reset();
+            tim.smcr().write(|w| {
+                w.etp().bit(config.edge == Edge::Falling);
+                w.etps().set(config.prescaler as u8);
+                w.etf().set(config.filter & 0xf);
+                w.ece().set_bit()
+            });
+            PulseCounter::_new(tim, pin)
+        }
+
+        fn $tiX<PIN>(
+            mut tim: $TIM,
+            pin: PIN,
+            ti2: bool,
+            config: TiConfig,
+        ) -> PulseCounter<$TIM, PIN> {
+            let filter = config.filter & 0xf;
+            let falling = config.edge == Edge::Falling;
+            tim.cr1_reset();
+            tim.ccer()
+                .modify(|_, w| w.cc1e().clear_bit().cc2e().clear_bit());
+            if ti2 {
+                tim.ccmr1_input()
+                    .modify(|_, w| w.cc2s().ti2().ic2f().set(filter));
+                tim.ccer().modify(|_, w| w.cc2p().bit(falling));
+            } else {
+                tim.ccmr1_input()
+                    .modify(|_, w| w.cc1s().ti1().ic1f().set(filter));
+                tim.ccer().modify(|_, w| w.cc1p().bit(falling));
+            }
+            // Select TI1FP1 (101) or TI2FP2 (110) as trigger input, external clock mode 1 (111)
+            tim.smcr().write(|w| unsafe {
+                w.ts().bits(if ti2 { 0b110 } else { 0b101 });
+                w.sms().bits(0b111)
+            });
+            PulseCounter::_new(tim, pin)
+        }
+
+        impl<PIN> PulseCounter<$TIM, PIN> {
+            /// Raises the `C4` interrupt once the count reaches `threshold`
+            ///
+            /// `threshold` is compared against the extended 32-bit count, so the interrupt
+            /// handler must call [`on_interrupt`](Self::on_interrupt), which also requires
+            /// the [`Event::Update`] interrupt to be enabled. The threshold is one-shot.
+            ///
+            /// Returns [`Error::ThresholdReached`], and leaves the interrupt disabled, if the
+            /// count is already at or above `threshold`.
+            pub fn listen_threshold(&mut self, threshold: u32) -> Result<(), Error> {
+                self.threshold = Some(threshold);
+                self.tim.ccmr2_output().modify(|_, w| {
+                    w.cc4s()
+                        .output()
+                        .oc4pe()
+                        .clear_bit()
+                        .oc4m()
+                        .set(Ocm::Frozen as u8)
+                });
+                self.tim.ccr4().write(|w| w.ccr().set(threshold as u16));
+                self.tim.clear_interrupt_flag(Event::C4);
+                self.tim.listen_interrupt(Event::Update | Event::C4, true);
+
+                // Checked once armed, so a pulse counted in between is not missed
+                let count = self.count();
+                check_threshold(count, threshold).map_err(|e| {
+                    self.unlisten_threshold();
+                    e
+                })
+            }
+
+            /// Disables the threshold interrupt
+            pub fn unlisten_threshold(&mut self) {
+                self.threshold = None;
+                self.tim.listen_interrupt(Event::C4, false);
+                self.tim.clear_interrupt_flag(Event::C4);
+            }
+
+            /// Handles the timer interrupt
+            ///
+            /// Accounts for a counter overflow and returns `true` if the threshold set by
+            /// [`listen_threshold`](Self::listen_threshold) has been reached.
+            pub fn on_interrupt(&mut self) -> bool {
+                self.handle_overflow();
+                if !self.tim.get_interrupt_flag().contains(Event::C4) {
+                    return false;
+                }
+                self.tim.clear_interrupt_flag(Event::C4);
+                let count = self.count();
+                match self.threshold {
+                    // The compare channel only sees the low 16 bits, so check we are in the right
+                    // 65536 pulses window
+                    Some(threshold) if count.wrapping_sub(threshold) < 0x1_0000 => {
+                        self.unlisten_threshold();
+                        true
+                    }
+                    _ => false,
+                }
+            }
+        }
+    };
+}
+
+#[cfg(any(feature = "stm32f100", feature = "stm32f103", feature = "connectivity"))]
+hal!(pac::TIM1: etr1, ti1);
+
+hal!(pac::TIM2: etr2, ti2);
+hal!(pac::TIM3: etr3, ti3);
+
+#[cfg(feature = "medium")]
+hal!(pac::TIM4: etr4, ti4);
+
+#[test]
+fn threshold_in_the_past() {
+    assert_eq!(check_threshold(999, 1000), Ok(()));
+    assert_eq!(check_threshold(1000, 1000), Err(Error::ThresholdReached));
+    assert_eq!(check_threshold(70_000, 1000), Err(Error::ThresholdReached));
+    assert_eq!(check_threshold(0, 0), Err(Error::ThresholdReached));
+}
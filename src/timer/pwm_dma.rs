@@ -0,0 +1,278 @@
+//! # PWM waveform generation with DMA
+//!
+//! The timer update event can request a DMA transfer, which is used to reload the duty cycle
+//! every PWM period from a buffer in memory. This is how pulse-coded protocols like WS2812
+//! LEDs or DShot ESCs are driven without CPU involvement.
+//!
+//! A single channel streams a `&[u16]` buffer into its `CCRx` register:
+//!
+//! ```rust,ignore
+//! let dma1 = dp.DMA1.split();
+//! let mut c1 = dp.TIM2.pwm_hz::<Tim2NoRemap, _, _>(pa0, &mut afio.mapr, 800.kHz(), &clocks).split();
+//! c1.enable();
+//!
+//! // TIM2_UP is served by DMA1 channel 2
+//! let transfer = c1.with_dma(dma1.2).write(buffer);
+//! let (buffer, c1_dma) = transfer.wait();
+//! ```
+//!
+//! Several consecutive channels are updated together with a DMA burst (`DCR`/`DMAR`): the buffer
+//! holds one duty cycle for each of the `len` channels starting at `first`, per PWM period.
+//!
+//! ```rust,ignore
+//! let pwm_dma = pwm.with_burst_dma(dma1.2, Channel::C1, 4);
+//! // c1, c2, c3, c4, c1, c2, ...
+//! let transfer = pwm_dma.write(buffer);
+//! ```
+//!
+//! The last value written stays in the compare register once the transfer is done, so end the
+//! buffer with the idle duty cycle (usually `0`).
+//!
+//! ## DMA requests of the timer update events
+//!
+//! | Timer | DMA channel  |
+//! |-------|--------------|
+//! | TIM1  | DMA1 C5      |
+//! | TIM2  | DMA1 C2      |
+//! | TIM3  | DMA1 C3      |
+//! | TIM4  | DMA1 C7      |
+//! | TIM5  | DMA2 C2      |
+//! | TIM8  | DMA2 C1      |
+//! | TIM15 | DMA1 C5      |
+//! | TIM16 | DMA1 C6      |
+//! | TIM17 | DMA1 C7      |
+
+use super::{Channel, Instance, Pins, Pwm, PwmChannel, PwmHz, Remap, WithDma};
+#[cfg(any(feature = "high", feature = "connectivity"))]
+use crate::dma::dma2;
+use crate::dma::{self, dma1, Ch, DmaExt, Transfer, TransferPayload, Transmit, TxDma};
+use crate::pac;
+use core::marker::PhantomData;
+use core::sync::atomic::{self, Ordering};
+use embedded_dma::ReadBuffer;
+
+/// DMA channel connected to the update event request of `TIM`
+pub trait UpdateDma<TIM> {}
+
+/// DMA burst payload updating `len` consecutive channels of a PWM timer
+pub struct PwmBurst<TIM, PWM> {
+    pwm: PWM,
+    first: Channel,
+    len: u8,
+    _tim: PhantomData<TIM>,
+}
+
+/// Single PWM channel fed by DMA
+pub type PwmChannelDma<TIM, const C: u8, CH> = TxDma<PwmChannel<TIM, C>, CH>;
+
+/// PWM timer updating several channels with DMA bursts
+pub type PwmBurstDma<TIM, PWM, CH> = TxDma<PwmBurst<TIM, PWM>, CH>;
+
+impl<TIM: Instance + WithDma, const C: u8> PwmChannel<TIM, C> {
+    /// Reloads the duty cycle from memory on each update event
+    pub fn with_dma<DMA: DmaExt, const CH: u8>(
+        self,
+        channel: Ch<DMA, CH>,
+    ) -> PwmChannelDma<TIM, C, Ch<DMA, CH>>
+    where
+        Ch<DMA, CH>: UpdateDma<TIM>,
+    {
+        TIM::enable_update_dma(true);
+        TxDma {
+            payload: self,
+            channel,
+        }
+    }
+}
+
+impl<TIM: Instance + WithDma, const C: u8, DMA: DmaExt, const CH: u8>
+    PwmChannelDma<TIM, C, Ch<DMA, CH>>
+{
+    pub fn release(self) -> (PwmChannel<TIM, C>, Ch<DMA, CH>) {
+        let TxDma { payload, channel } = self;
+        TIM::enable_update_dma(false);
+        (payload, channel)
+    }
+}
+
+impl<TIM, REMAP, P, PINS> PwmHz<TIM, REMAP, P, PINS>
+where
+    TIM: Instance + WithDma,
+    REMAP: Remap<Periph = TIM>,
+    PINS: Pins<REMAP, P>,
+{
+    /// Reloads the duty cycles of `len` channels starting at `first` on each update event
+    pub fn with_burst_dma<DMA: DmaExt, const CH: u8>(
+        self,
+        channel: Ch<DMA, CH>,
+        first: Channel,
+        len: u8,
+    ) -> PwmBurstDma<TIM, Self, Ch<DMA, CH>>
+    where
+        Ch<DMA, CH>: UpdateDma<TIM>,
+    {
+        PwmBurst::new(self, first, len).with_dma(channel)
+    }
+}
+
+impl<TIM, REMAP, P, PINS, const FREQ: u32> Pwm<TIM, REMAP, P, PINS, FREQ>
+where
+    TIM: Instance + WithDma,
+    REMAP: Remap<Periph = TIM>,
+    PINS: Pins<REMAP, P>,
+{
+    /// Reloads the duty cycles of `len` channels starting at `first` on each update event
+    pub fn with_burst_dma<DMA: DmaExt, const CH: u8>(
+        self,
+        channel: Ch<DMA, CH>,
+        first: Channel,
+        len: u8,
+    ) -> PwmBurstDma<TIM, Self, Ch<DMA, CH>>
+    where
+        Ch<DMA, CH>: UpdateDma<TIM>,
+    {
+        PwmBurst::new(self, first, len).with_dma(channel)
+    }
+}
+
+impl<TIM: Instance + WithDma, PWM> PwmBurst<TIM, PWM> {
+    fn new(pwm: PWM, first: Channel, len: u8) -> Self {
+        assert!(len > 0 && first as u8 + len <= TIM::CH_NUMBER);
+        Self {
+            pwm,
+            first,
+            len,
+            _tim: PhantomData,
+        }
+    }
+
+    fn with_dma<CH>(self, channel: CH) -> PwmBurstDma<TIM, PWM, CH> {
+        TIM::set_dma_burst(self.first as u8, self.len);
+        TIM::enable_update_dma(true);
+        TxDma {
+            payload: self,
+            channel,
+        }
+    }
+}
+
+impl<TIM: Instance + WithDma, PWM, DMA: DmaExt, const CH: u8> PwmBurstDma<TIM, PWM, Ch<DMA, CH>> {
+    pub fn release(self) -> (PWM, Ch<DMA, CH>) {
+        let TxDma { payload, channel } = self;
+        TIM::enable_update_dma(false);
+        TIM::set_dma_burst(0, 1);
+        (payload.pwm, channel)
+    }
+}
+
+impl<PAYLOAD, DMA: DmaExt, const CH: u8> Transmit for TxDma<PAYLOAD, Ch<DMA, CH>>
+where
+    Self: PwmDmaPayload,
+{
+    type TxChannel = Ch<DMA, CH>;
+    type ReceivedWord = u16;
+}
+
+impl<PAYLOAD, DMA: DmaExt, const CH: u8> TransferPayload for TxDma<PAYLOAD, Ch<DMA, CH>>
+where
+    Self: PwmDmaPayload,
+{
+    fn start(&mut self) {
+        self.channel.start();
+    }
+    fn stop(&mut self) {
+        self.channel.stop();
+    }
+}
+
+impl<B, PAYLOAD, DMA: DmaExt, const CH: u8> dma::WriteDma<B, u16> for TxDma<PAYLOAD, Ch<DMA, CH>>
+where
+    Self: PwmDmaPayload,
+    B: ReadBuffer<Word = u16>,
+{
+    fn write(mut self, buffer: B) -> Transfer<dma::R, B, Self> {
+        // NOTE(unsafe) We own the buffer now and we won't call other `&mut` on it
+        // until the end of the transfer.
+        let (ptr, len) = unsafe { buffer.read_buffer() };
+        assert_eq!(len % self.words_per_update(), 0);
+        self.channel
+            .set_peripheral_address(self.peripheral_address(), false);
+        self.channel.set_memory_address(ptr as u32, true);
+        self.channel.set_transfer_length(len);
+
+        atomic::compiler_fence(Ordering::Release);
+        self.channel.ch().cr().modify(|_, w| {
+            // memory to memory mode disabled
+            w.mem2mem().clear_bit();
+            // high channel priority level, a late request glitches the waveform
+            w.pl().high();
+            // 16-bit memory size
+            w.msize().bits16();
+            // 16-bit peripheral size
+            w.psize().bits16();
+            // circular mode disabled
+            w.circ().clear_bit();
+            // read from memory
+            w.dir().set_bit()
+        });
+        self.start();
+
+        Transfer::r(buffer, self)
+    }
+}
+
+/// Destination of the DMA writes
+pub trait PwmDmaPayload {
+    fn peripheral_address(&self) -> u32;
+    fn words_per_update(&self) -> usize;
+}
+
+impl<TIM: Instance + WithDma, const C: u8, CH> PwmDmaPayload for PwmChannelDma<TIM, C, CH> {
+    fn peripheral_address(&self) -> u32 {
+        TIM::ccr_address(C)
+    }
+    fn words_per_update(&self) -> usize {
+        1
+    }
+}
+
+impl<TIM: Instance + WithDma, PWM, CH> PwmDmaPayload for PwmBurstDma<TIM, PWM, CH> {
+    fn peripheral_address(&self) -> u32 {
+        TIM::dmar_address()
+    }
+    fn words_per_update(&self) -> usize {
+        self.payload.len as usize
+    }
+}
+
+macro_rules! update_dma {
+    ($($TIM:ty: $dmaX:ident::$CX:ident,)+) => {
+        $(
+            impl UpdateDma<$TIM> for $dmaX::$CX {}
+        )+
+    };
+}
+
+#[cfg(any(feature = "stm32f100", feature = "stm32f103", feature = "connectivity"))]
+update_dma!(pac::TIM1: dma1::C5,);
+
+update_dma!(
+    pac::TIM2: dma1::C2,
+    pac::TIM3: dma1::C3,
+);
+
+#[cfg(feature = "medium")]
+update_dma!(pac::TIM4: dma1::C7,);
+
+#[cfg(any(feature = "high", feature = "connectivity"))]
+update_dma!(pac::TIM5: dma2::C2,);
+
+#[cfg(all(feature = "stm32f103", feature = "high"))]
+update_dma!(pac::TIM8: dma2::C1,);
+
+#[cfg(feature = "stm32f100")]
+update_dma!(
+    pac::TIM15: dma1::C5,
+    pac::TIM16: dma1::C6,
+    pac::TIM17: dma1::C7,
+);
@@ -1,14 +1,17 @@
 //! This module allows Timer peripherals to be configured as pwm input.
 //! In this mode, the timer sample a squared signal to find it's frequency and duty cycle.
 //!
-//! Also this module provides Quadrature Encoder Interface
+//! Also this module provides Quadrature Encoder Interface, with optional index (Z) input,
+//! position extended to `i64` in software and a [`VelocityEstimator`] sampling on another timer.
 
 use crate::pac::{self, DBGMCU as DBG};
 
 use crate::afio::{RInto, Rmp, TimC};
 use crate::rcc::{BusTimerClock, Clocks};
 use crate::time::Hertz;
-use crate::timer::{General, Timer};
+use crate::timer::{self, CounterHz, Event, General, Timer};
+
+pub use crate::timer::SlaveMode;
 
 use embedded_hal_02 as hal;
 pub use hal::Direction;
@@ -72,26 +75,6 @@ pub enum Configuration {
     RawValues { arr: u16, presc: u16 },
 }
 
-/// SMS (Slave Mode Selection) register
-#[derive(Copy, Clone, Debug)]
-pub enum SlaveMode {
-    /// Counter counts up/down on TI2FP1 edge depending on TI1FP2 level.
-    EncoderMode1 = 0b001,
-    /// Encoder mode 2 - Counter counts up/down on TI1FP2 edge depending on TI2FP1 level.
-    EncoderMode2 = 0b010,
-    /// Encoder mode 3 - Counter counts up/down on both TI1FP1 and TI2FP2 edges depending on the
-    /// level of the other input.
-    EncoderMode3 = 0b011,
-    /// Reset Mode - Rising edge of the selected trigger input (TRGI) reinitializes the counter and
-    /// generates an update of the registers.
-    ResetMode = 0b100,
-    /// Trigger Mode - The counter starts at a rising edge of the trigger TRGI (but it is not
-    /// reset). Only the start of the counter is controlled.
-    TriggerMode = 0b110,
-    /// External Clock Mode 1 - Rising edges of the selected trigger (TRGI) clock the counter.
-    ExternalClockMode1 = 0b111,
-}
-
 /// Quadrature Encoder Interface (QEI) options
 ///
 /// The `Default` implementation provides a configuration for a 4-count pulse which counts from
@@ -106,6 +89,11 @@ pub struct QeiOptions {
     /// This value allows the maximum count to be configured, up to 65535. Setting a lower value
     /// will overflow the counter to 0 sooner.
     pub auto_reload_value: u16,
+
+    /// Digital filter of the encoder inputs (`ICxF`, 0 to 15)
+    ///
+    /// Also applied to the index input.
+    pub input_filter: u8,
 }
 
 impl Default for QeiOptions {
@@ -113,14 +101,58 @@ impl Default for QeiOptions {
         Self {
             slave_mode: SlaveMode::EncoderMode3,
             auto_reload_value: u16::MAX,
+            input_filter: 0,
         }
     }
 }
 
+impl QeiOptions {
+    /// Sets the digital filter of the encoder inputs (`ICxF`, 0 to 15)
+    pub fn input_filter(mut self, filter: u8) -> Self {
+        self.input_filter = filter;
+        self
+    }
+}
+
+/// What happens to the position on an index (Z) pulse
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IndexMode {
+    /// The position at the index pulse is only recorded
+    Latch,
+    /// The position is set to zero at the index pulse
+    Reset,
+}
+
 /// Quadrature Encoder Interface (QEI)
-pub struct Qei<TIM: Instance> {
+///
+/// The hardware counter is 16-bit wide, [`position`](Self::position) extends it to `i64` when
+/// the overflow interrupt is enabled with [`listen_overflow`](Self::listen_overflow) and
+/// [`on_interrupt`](Self::on_interrupt) is called from the timer interrupt handler.
+pub struct Qei<TIM: Instance, INDEX = ()> {
     tim: TIM,
     pins: (<TIM as TimC<0>>::In, <TIM as TimC<1>>::In),
+    index: INDEX,
+    index_mode: IndexMode,
+    offset: i64,
+    last_index: Option<i64>,
+}
+
+/// Encoder speed estimated from the position difference between two samples
+///
+/// The samples are taken in the update interrupt of a separate timer, which sets the sampling
+/// rate:
+///
+/// ```rust,ignore
+/// let mut velocity = VelocityEstimator::new(dp.TIM2.counter_hz(&clocks), 100.Hz());
+/// // in TIM2 interrupt
+/// velocity.sample(qei.position());
+/// let counts_per_second = velocity.counts_per_second();
+/// ```
+pub struct VelocityEstimator<TIM> {
+    counter: CounterHz<TIM>,
+    rate: Hertz,
+    last: Option<i64>,
+    delta: i64,
 }
 
 pub trait PwmInputExt: Sized + Instance {
@@ -393,7 +425,11 @@ macro_rules! hal {
         ) -> Qei<$TIM> {
             let pins = (pins.0.rinto(), pins.1.rinto());
             // Configure TxC1 and TxC2 as captures
-            tim.ccmr1_input().write(|w| w.cc1s().ti1().cc2s().ti2());
+            let filter = options.input_filter & 0xf;
+            tim.ccmr1_input().write(|w| {
+                w.cc1s().ti1().ic1f().set(filter);
+                w.cc2s().ti2().ic2f().set(filter)
+            });
 
             // enable and configure to capture on rising edge
             tim.ccer().write(|w| {
@@ -409,16 +445,164 @@ macro_rules! hal {
             tim.arr().write(|w| w.arr().set(options.auto_reload_value));
             tim.cr1().write(|w| w.cen().set_bit());
 
-            Qei { tim, pins }
+            Qei {
+                tim,
+                pins,
+                index: (),
+                index_mode: IndexMode::Latch,
+                offset: 0,
+                last_index: None,
+            }
         }
 
         impl Qei<$TIM> {
+            /// Captures the counter on the rising edge of the index (Z) pin, connected to channel 3
+            ///
+            /// The capture interrupt is enabled, call [`on_interrupt`](Self::on_interrupt) from
+            /// the timer interrupt handler.
+            pub fn with_index(
+                self,
+                pin: impl RInto<<$TIM as TimC<2>>::In, 0>,
+                mode: IndexMode,
+            ) -> Qei<$TIM, <$TIM as TimC<2>>::In> {
+                self.with_remapped_index::<0>(pin, mode)
+            }
+
+            /// Same as [`with_index`](Self::with_index) for a timer created with remap `R`
+            pub fn with_remapped_index<const R: u8>(
+                self,
+                pin: impl RInto<<$TIM as TimC<2>>::In, R>,
+                mode: IndexMode,
+            ) -> Qei<$TIM, <$TIM as TimC<2>>::In> {
+                let index = pin.rinto();
+                let mut tim = self.tim;
+                let filter = tim.ccmr1_input().read().ic1f().bits();
+                tim.ccmr2_input()
+                    .modify(|_, w| w.cc3s().ti3().ic3f().set(filter));
+                tim.ccer()
+                    .modify(|_, w| w.cc3p().clear_bit().cc3e().set_bit());
+                tim.clear_interrupt_flag(Event::C3);
+                tim.dier().modify(|_, w| w.cc3ie().set_bit());
+                Qei {
+                    tim,
+                    pins: self.pins,
+                    index,
+                    index_mode: mode,
+                    offset: self.offset,
+                    last_index: None,
+                }
+            }
+
             pub fn release(self) -> ($TIM, (<$TIM as TimC<0>>::In, <$TIM as TimC<1>>::In)) {
                 (self.tim, self.pins)
             }
         }
 
-        impl hal::Qei for Qei<$TIM> {
+        impl Qei<$TIM, <$TIM as TimC<2>>::In> {
+            pub fn release(
+                self,
+            ) -> (
+                $TIM,
+                (<$TIM as TimC<0>>::In, <$TIM as TimC<1>>::In),
+                <$TIM as TimC<2>>::In,
+            ) {
+                self.tim.ccer().modify(|_, w| w.cc3e().clear_bit());
+                self.tim.dier().modify(|_, w| w.cc3ie().clear_bit());
+                (self.tim, self.pins, self.index)
+            }
+
+            /// Position latched at the last index pulse, before any reset
+            pub fn last_index(&self) -> Option<i64> {
+                self.last_index
+            }
+        }
+
+        impl<INDEX> Qei<$TIM, INDEX> {
+            /// Enables the update interrupt used to extend the position past 16 bits
+            pub fn listen_overflow(&mut self) {
+                self.tim.clear_interrupt_flag(Event::Update);
+                self.tim.dier().modify(|_, w| w.uie().set_bit());
+            }
+
+            /// Disables the update interrupt
+            pub fn unlisten_overflow(&mut self) {
+                self.tim.dier().modify(|_, w| w.uie().clear_bit());
+            }
+
+            /// Returns the extended position
+            ///
+            /// An overflow not handled yet by [`on_interrupt`](Self::on_interrupt) is accounted
+            /// for, as long as the counter has not moved by more than half a period since.
+            pub fn position(&self) -> i64 {
+                let cnt = self.tim.cnt().read().cnt().bits() as i64;
+                if self.tim.sr().read().uif().bit_is_clear() {
+                    return self.offset + cnt;
+                }
+                // Read again as the overflow may have happened after the first read
+                let cnt = self.tim.cnt().read().cnt().bits() as i64;
+                self.offset + self.wrap_adjust(cnt) + cnt
+            }
+
+            /// Makes the current position equal to `position`
+            pub fn set_position(&mut self, position: i64) {
+                self.on_overflow();
+                self.offset = position - self.tim.cnt().read().cnt().bits() as i64;
+            }
+
+            /// Handles the timer interrupt
+            ///
+            /// Accounts for a counter overflow or underflow and returns the position at the
+            /// index pulse if one was captured.
+            pub fn on_interrupt(&mut self) -> Option<i64> {
+                self.on_overflow();
+                if self.tim.sr().read().cc3if().bit_is_clear()
+                    || self.tim.dier().read().cc3ie().bit_is_clear()
+                {
+                    return None;
+                }
+                // Reading the captured value clears the flag
+                let cap = self.tim.ccr3().read().ccr().bits() as i64;
+                let cnt = self.tim.cnt().read().cnt().bits() as i64;
+                let period = self.period();
+                // The counter may have wrapped between the capture and now
+                let mut position = self.offset + cap;
+                if cap - cnt > period / 2 {
+                    position -= period;
+                } else if cnt - cap > period / 2 {
+                    position += period;
+                }
+                if self.index_mode == IndexMode::Reset {
+                    self.offset -= position;
+                }
+                self.last_index = Some(position);
+                Some(position)
+            }
+
+            fn on_overflow(&mut self) {
+                if self.tim.sr().read().uif().bit_is_set() {
+                    self.tim.clear_interrupt_flag(Event::Update);
+                    let cnt = self.tim.cnt().read().cnt().bits() as i64;
+                    self.offset += self.wrap_adjust(cnt);
+                }
+            }
+
+            fn period(&self) -> i64 {
+                self.tim.arr().read().arr().bits() as i64 + 1
+            }
+
+            /// The update event happens on both overflow and underflow: the counter is in the
+            /// lower half of its range right after an overflow
+            fn wrap_adjust(&self, cnt: i64) -> i64 {
+                let period = self.period();
+                if cnt < period / 2 {
+                    period
+                } else {
+                    -period
+                }
+            }
+        }
+
+        impl<INDEX> hal::Qei for Qei<$TIM, INDEX> {
             type Count = u16;
 
             fn count(&self) -> u16 {
@@ -436,6 +620,53 @@ macro_rules! hal {
     };
 }
 
+impl<TIM: timer::Instance> VelocityEstimator<TIM> {
+    /// Starts `counter` at `rate` and enables its update interrupt
+    pub fn new(mut counter: CounterHz<TIM>, rate: Hertz) -> Self {
+        counter.start(rate).unwrap();
+        counter.listen(Event::Update);
+        // The actual rate may be slightly different from the requested one
+        let psc = counter.psc() as u32 + 1;
+        let arr = counter.arr() as u32 + 1;
+        let rate = Hertz::from_raw(counter.clk.raw() / psc / arr);
+        Self {
+            counter,
+            rate,
+            last: None,
+            delta: 0,
+        }
+    }
+
+    /// Records the encoder `position`, to be called from the update interrupt of the timer
+    pub fn sample(&mut self, position: i64) {
+        self.counter.clear_interrupt(Event::Update);
+        if let Some(last) = self.last {
+            self.delta = position - last;
+        }
+        self.last = Some(position);
+    }
+
+    /// Position change between the last two samples
+    pub fn counts_per_sample(&self) -> i64 {
+        self.delta
+    }
+
+    /// Speed in encoder counts per second
+    pub fn counts_per_second(&self) -> i64 {
+        self.delta * self.rate.raw() as i64
+    }
+
+    /// Sampling rate
+    pub fn rate(&self) -> Hertz {
+        self.rate
+    }
+
+    pub fn release(mut self) -> CounterHz<TIM> {
+        self.counter.unlisten(Event::Update);
+        self.counter
+    }
+}
+
 #[cfg(any(feature = "stm32f100", feature = "stm32f103", feature = "connectivity"))]
 hal!(pac::TIM1: tim1, qei1);
 
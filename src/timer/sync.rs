@@ -0,0 +1,362 @@
+/*!
+  # Timer synchronization
+
+  Timers can be chained together: the trigger output (`TRGO`) of a master timer is
+  internally connected to the `ITRx` trigger inputs of other timers. The slave timer can then
+  be reset, gated, started or clocked by the master (see [SlaveMode]).
+
+  [Timer::set_slave_of] selects the right `ITRx` input from the tables below, so only
+  existing connections compile.
+
+  ## Internal trigger connections
+
+  | Slave | ITR0 | ITR1 | ITR2 | ITR3 |
+  |:-----:|:----:|:----:|:----:|:----:|
+  | TIM1  | TIM5 | TIM2 | TIM3 | TIM4 |
+  | TIM2  | TIM1 | TIM8 | TIM3 | TIM4 |
+  | TIM3  | TIM1 | TIM2 | TIM5 | TIM4 |
+  | TIM4  | TIM1 | TIM2 | TIM3 | TIM8 |
+  | TIM5  | TIM2 | TIM3 | TIM4 | TIM8 |
+  | TIM8  | TIM1 | TIM2 | TIM4 | TIM5 |
+
+  On connectivity line devices (STM32F105/STM32F107) `ITR1` of TIM2 is connected to the
+  Ethernet PTP or USB OTG SOF output instead of TIM8.
+
+  ### STM32F100
+
+  | Slave | ITR0  | ITR1  | ITR2     | ITR3     |
+  |:-----:|:-----:|:-----:|:--------:|:--------:|
+  | TIM1  | TIM15 | TIM2  | TIM3     | TIM4     |
+  | TIM2  | TIM1  | TIM15 | TIM3     | TIM4     |
+  | TIM3  | TIM1  | TIM2  | TIM15    | TIM4     |
+  | TIM4  | TIM1  | TIM2  | TIM3     | TIM15    |
+  | TIM5  | TIM2  | TIM3  | TIM4     | TIM15    |
+  | TIM15 | TIM2  | TIM3  | TIM16_OC | TIM17_OC |
+
+  TIM15, TIM16 and TIM17 are not supported as master timers, use
+  [Timer::set_slave_mode] with a raw [TriggerSource] to connect them.
+
+  ## Usage
+
+  Start TIM3 and TIM4 at the same time as TIM2:
+
+  ```rust
+  let mut pwm3 = dp.TIM3.pwm_hz(pins3, &mut afio.mapr, 1.kHz(), &clocks);
+  let mut pwm4 = dp.TIM4.pwm_hz(pins4, &mut afio.mapr, 1.kHz(), &clocks);
+  // Counters are stopped until TIM2 is enabled
+  pwm3.set_slave_of::<pac::TIM2>(SlaveMode::TriggerMode);
+  pwm4.set_slave_of::<pac::TIM2>(SlaveMode::TriggerMode);
+
+  let mut counter = dp.TIM2.counter_hz(&clocks);
+  counter.start(1.kHz()).unwrap();
+  counter.start_synchronized();
+  ```
+
+  Use TIM2 as prescaler of TIM3 to get a 32-bit counter:
+
+  ```rust
+  let master = FTimerUs::new(dp.TIM2, &clocks);
+  let slave = Timer::new(dp.TIM3, &clocks);
+  let counter = ChainedCounter::new(master, slave);
+  let t: fugit::TimerInstantU32<1_000_000> = counter.now();
+  ```
+*/
+
+use super::{FTimer, Instance, MasterTimer, SlaveTimer, Timer};
+use crate::pac;
+use fugit::TimerInstantU32;
+
+/// SMS (Slave Mode Selection) register
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlaveMode {
+    /// Slave mode disabled - the prescaler is clocked directly by the internal clock.
+    Disabled = 0b000,
+    /// Counter counts up/down on TI2FP1 edge depending on TI1FP2 level.
+    EncoderMode1 = 0b001,
+    /// Encoder mode 2 - Counter counts up/down on TI1FP2 edge depending on TI2FP1 level.
+    EncoderMode2 = 0b010,
+    /// Encoder mode 3 - Counter counts up/down on both TI1FP1 and TI2FP2 edges depending on the
+    /// level of the other input.
+    EncoderMode3 = 0b011,
+    /// Reset Mode - Rising edge of the selected trigger input (TRGI) reinitializes the counter and
+    /// generates an update of the registers.
+    ResetMode = 0b100,
+    /// Gated Mode - The counter clock is enabled when the trigger input (TRGI) is high. The
+    /// counter stops (but is not reset) as soon as the trigger becomes low.
+    GatedMode = 0b101,
+    /// Trigger Mode - The counter starts at a rising edge of the trigger TRGI (but it is not
+    /// reset). Only the start of the counter is controlled.
+    TriggerMode = 0b110,
+    /// External Clock Mode 1 - Rising edges of the selected trigger (TRGI) clock the counter.
+    ExternalClockMode1 = 0b111,
+}
+
+/// TS (Trigger Selection) register
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriggerSource {
+    /// Internal Trigger 0
+    Itr0 = 0b000,
+    /// Internal Trigger 1
+    Itr1 = 0b001,
+    /// Internal Trigger 2
+    Itr2 = 0b010,
+    /// Internal Trigger 3
+    Itr3 = 0b011,
+    /// TI1 Edge Detector
+    Ti1FEd = 0b100,
+    /// Filtered Timer Input 1
+    Ti1Fp1 = 0b101,
+    /// Filtered Timer Input 2
+    Ti2Fp2 = 0b110,
+    /// External Trigger input
+    Etrf = 0b111,
+}
+
+/// Internal trigger (`ITRx`) connection from the trigger output of `MASTER`
+pub trait InternalTrigger<MASTER>: SlaveTimer {
+    const ITR: TriggerSource;
+}
+
+macro_rules! sync {
+    ($([$($FREQ:tt)*] $Timer:ty),+) => {
+        $(
+            impl<TIM: Instance + SlaveTimer, $($FREQ)*> $Timer {
+                /// Selects the trigger input and the slave mode of the timer
+                ///
+                /// In [SlaveMode::TriggerMode] the counter is also stopped and reset, so it starts
+                /// counting on the next rising edge of the trigger.
+                pub fn set_slave_mode(&mut self, trigger: TriggerSource, mode: SlaveMode) {
+                    if mode == SlaveMode::TriggerMode {
+                        self.tim.disable_counter();
+                        self.tim.reset_counter();
+                    }
+                    self.tim.set_slave_mode(trigger, mode);
+                }
+
+                /// Connects the trigger input of the timer to the trigger output of `MASTER`
+                pub fn set_slave_of<MASTER>(&mut self, mode: SlaveMode)
+                where
+                    MASTER: MasterTimer,
+                    TIM: InternalTrigger<MASTER>,
+                {
+                    self.set_slave_mode(<TIM as InternalTrigger<MASTER>>::ITR, mode);
+                }
+
+                /// Delays the effect of the trigger input to allow a perfect synchronization
+                /// between this timer and its own slaves (MSM bit)
+                pub fn enable_master_slave_mode(&mut self, b: bool) {
+                    self.tim.enable_master_slave_mode(b);
+                }
+            }
+
+            impl<TIM: Instance + MasterTimer, $($FREQ)*> $Timer {
+                /// Restarts the counter together with all the timers waiting for it in
+                /// [SlaveMode::TriggerMode]
+                ///
+                /// NOTE: this overrides the master mode with `MMS = Enable`
+                pub fn start_synchronized(&mut self) {
+                    self.tim.disable_counter();
+                    self.tim.reset_counter();
+                    self.tim.master_mode(TIM::MMS_ENABLE);
+                    self.tim.enable_counter();
+                }
+            }
+        )+
+    };
+}
+
+sync!([] Timer<TIM>, [const FREQ: u32] FTimer<TIM, FREQ>);
+
+/// 32-bit counter made of two chained 16-bit timers
+///
+/// `MASTER` counts with precision of `FREQ` and clocks `SLAVE` on each overflow through
+/// its update event, so `SLAVE` holds the upper 16 bits of the count.
+pub struct ChainedCounter<MASTER, SLAVE, const FREQ: u32> {
+    master: FTimer<MASTER, FREQ>,
+    slave: Timer<SLAVE>,
+}
+
+/// `ChainedCounter` with precision of 1 μs (1 MHz sampling)
+///
+/// Wraps after about 71 minutes.
+pub type ChainedCounterUs<MASTER, SLAVE> = ChainedCounter<MASTER, SLAVE, 1_000_000>;
+
+impl<MASTER, SLAVE, const FREQ: u32> ChainedCounter<MASTER, SLAVE, FREQ>
+where
+    MASTER: Instance + MasterTimer,
+    SLAVE: Instance + InternalTrigger<MASTER>,
+{
+    /// Chains and starts both timers
+    pub fn new(mut master: FTimer<MASTER, FREQ>, mut slave: Timer<SLAVE>) -> Self {
+        master.tim.disable_counter();
+        master.tim.set_auto_reload(0xffff).unwrap();
+        // Load the prescaler before the update event is routed to the slave
+        master.tim.trigger_update();
+        master.tim.master_mode(MASTER::MMS_UPDATE);
+
+        slave.tim.disable_counter();
+        slave.tim.set_prescaler(0);
+        slave.tim.set_auto_reload(0xffff).unwrap();
+        slave.tim.trigger_update();
+        slave.set_slave_of::<MASTER>(SlaveMode::ExternalClockMode1);
+
+        master.tim.reset_counter();
+        slave.tim.reset_counter();
+        slave.tim.enable_counter();
+        master.tim.enable_counter();
+
+        Self { master, slave }
+    }
+
+    /// Current value of the 32-bit counter
+    pub fn now(&self) -> TimerInstantU32<FREQ> {
+        loop {
+            let high: u32 = self.slave.tim.read_count().into();
+            let low: u32 = self.master.tim.read_count().into();
+            // Retry if the master overflowed between the two reads
+            if high == self.slave.tim.read_count().into() {
+                return TimerInstantU32::from_ticks((high << 16) | low);
+            }
+        }
+    }
+
+    /// Stops both timers and releases them
+    pub fn release(mut self) -> (FTimer<MASTER, FREQ>, Timer<SLAVE>) {
+        self.master.tim.cr1_reset();
+        self.slave.tim.cr1_reset();
+        self.slave
+            .tim
+            .set_slave_mode(TriggerSource::Itr0, SlaveMode::Disabled);
+        (self.master, self.slave)
+    }
+}
+
+macro_rules! slave {
+    ($TIM:ty: [$($(#[$attr:meta])* $ITR:ident: $MASTER:ty,)*]) => {
+        impl SlaveTimer for $TIM {
+            #[inline(always)]
+            fn set_slave_mode(&mut self, trigger: TriggerSource, mode: SlaveMode) {
+                #[allow(unused_unsafe)]
+                self.smcr()
+                    .modify(|_, w| unsafe { w.ts().bits(trigger as u8).sms().bits(mode as u8) });
+            }
+            #[inline(always)]
+            fn enable_master_slave_mode(&mut self, b: bool) {
+                self.smcr().modify(|_, w| w.msm().bit(b));
+            }
+        }
+
+        $(
+            $(#[$attr])*
+            impl InternalTrigger<$MASTER> for $TIM {
+                const ITR: TriggerSource = TriggerSource::$ITR;
+            }
+        )*
+    };
+}
+
+#[cfg(not(feature = "stm32f100"))]
+slave!(pac::TIM2: [
+    #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+    Itr0: pac::TIM1,
+    #[cfg(all(feature = "stm32f103", feature = "high"))]
+    Itr1: pac::TIM8,
+    Itr2: pac::TIM3,
+    #[cfg(feature = "medium")]
+    Itr3: pac::TIM4,
+]);
+
+#[cfg(not(feature = "stm32f100"))]
+slave!(pac::TIM3: [
+    #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+    Itr0: pac::TIM1,
+    Itr1: pac::TIM2,
+    #[cfg(any(feature = "high", feature = "connectivity"))]
+    Itr2: pac::TIM5,
+    #[cfg(feature = "medium")]
+    Itr3: pac::TIM4,
+]);
+
+#[cfg(all(feature = "medium", not(feature = "stm32f100")))]
+slave!(pac::TIM4: [
+    #[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+    Itr0: pac::TIM1,
+    Itr1: pac::TIM2,
+    Itr2: pac::TIM3,
+    #[cfg(all(feature = "stm32f103", feature = "high"))]
+    Itr3: pac::TIM8,
+]);
+
+#[cfg(all(
+    any(feature = "high", feature = "connectivity"),
+    not(feature = "stm32f100")
+))]
+slave!(pac::TIM5: [
+    Itr0: pac::TIM2,
+    Itr1: pac::TIM3,
+    Itr2: pac::TIM4,
+    #[cfg(all(feature = "stm32f103", feature = "high"))]
+    Itr3: pac::TIM8,
+]);
+
+#[cfg(any(feature = "stm32f103", feature = "connectivity"))]
+slave!(pac::TIM1: [
+    #[cfg(any(feature = "high", feature = "connectivity"))]
+    Itr0: pac::TIM5,
+    Itr1: pac::TIM2,
+    Itr2: pac::TIM3,
+    #[cfg(feature = "medium")]
+    Itr3: pac::TIM4,
+]);
+
+#[cfg(all(feature = "stm32f103", feature = "high"))]
+slave!(pac::TIM8: [
+    Itr0: pac::TIM1,
+    Itr1: pac::TIM2,
+    Itr2: pac::TIM4,
+    Itr3: pac::TIM5,
+]);
+
+#[cfg(feature = "stm32f100")]
+slave!(pac::TIM1: [
+    Itr1: pac::TIM2,
+    Itr2: pac::TIM3,
+    #[cfg(feature = "medium")]
+    Itr3: pac::TIM4,
+]);
+
+#[cfg(feature = "stm32f100")]
+slave!(pac::TIM2: [
+    Itr0: pac::TIM1,
+    Itr2: pac::TIM3,
+    #[cfg(feature = "medium")]
+    Itr3: pac::TIM4,
+]);
+
+#[cfg(feature = "stm32f100")]
+slave!(pac::TIM3: [
+    Itr0: pac::TIM1,
+    Itr1: pac::TIM2,
+    #[cfg(feature = "medium")]
+    Itr3: pac::TIM4,
+]);
+
+#[cfg(all(feature = "stm32f100", feature = "medium"))]
+slave!(pac::TIM4: [
+    Itr0: pac::TIM1,
+    Itr1: pac::TIM2,
+    Itr2: pac::TIM3,
+]);
+
+#[cfg(all(feature = "stm32f100", feature = "high"))]
+slave!(pac::TIM5: [
+    Itr0: pac::TIM2,
+    Itr1: pac::TIM3,
+    Itr2: pac::TIM4,
+]);
+
+#[cfg(feature = "stm32f100")]
+slave!(pac::TIM15: [
+    Itr0: pac::TIM2,
+    Itr1: pac::TIM3,
+]);
@@ -0,0 +1,231 @@
+//! `embassy-time` driver
+//!
+//! The 16-bit counter runs at `embassy_time_driver::TICK_HZ` (selected with the `tick-hz-*`
+//! features of `embassy-time`) and is extended to 64 bits with a half-period counter: the `C1`
+//! compare event fires in the middle of the period and the update event at its end. Alarms use
+//! the `C2` compare channel.
+//!
+//! The driver is registered by the application, which also forwards the timer interrupt:
+//!
+//! ```rust,ignore
+//! embassy_time_driver::time_driver_impl!(static DRIVER: TimeDriver<pac::TIM2> = TimeDriver::new());
+//!
+//! DRIVER.start(dp.TIM2, &clocks);
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     DRIVER.on_interrupt();
+//! }
+//! ```
+
+use super::{Event, Instance, Timer, WithPwm};
+use crate::pac;
+use crate::rcc::Clocks;
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+use core::task::Waker;
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::NVIC;
+use embassy_time_driver::{Driver, TICK_HZ};
+use embassy_time_queue_utils::Queue;
+
+mod sealed {
+    pub trait WithTimeDriver: super::Instance + super::WithPwm + Sized + 'static {
+        const IRQ: super::pac::Interrupt;
+        unsafe fn steal() -> Self;
+    }
+}
+pub(crate) use sealed::WithTimeDriver;
+
+/// Time left to the alarm below which the `C2` interrupt is enabled
+///
+/// The compare register only holds the low 16 bits of the alarm, so it must not be
+/// enabled more than one period in advance. Checked at each half period.
+const ALARM_WINDOW: u64 = 0xc000;
+
+struct AlarmState {
+    queue: Queue,
+    at: u64,
+}
+
+/// `embassy-time` driver running on `TIM`
+pub struct TimeDriver<TIM> {
+    /// Number of half periods elapsed
+    period: AtomicU32,
+    state: Mutex<RefCell<AlarmState>>,
+    _tim: PhantomData<fn() -> TIM>,
+}
+
+impl<TIM: WithTimeDriver> TimeDriver<TIM> {
+    pub const fn new() -> Self {
+        Self {
+            period: AtomicU32::new(0),
+            state: Mutex::new(RefCell::new(AlarmState {
+                queue: Queue::new(),
+                at: u64::MAX,
+            })),
+            _tim: PhantomData,
+        }
+    }
+
+    /// Starts the time base
+    ///
+    /// The timer clock must be a multiple of `TICK_HZ` and `TICK_HZ` must be at least 1/65536
+    /// of it. The timer interrupt is unmasked in the NVIC, its priority is left to the
+    /// application.
+    pub fn start(&self, tim: TIM, clocks: &Clocks) {
+        let Timer { mut tim, clk } = Timer::new(tim, clocks);
+        let clk = clk.raw() as u64;
+        assert!(clk % TICK_HZ == 0);
+        let psc = u16::try_from(clk / TICK_HZ - 1).unwrap();
+
+        tim.set_prescaler(psc);
+        // Safety: u16::MAX is always a valid auto-reload value
+        unsafe { tim.set_auto_reload_unchecked(u16::MAX as u32) };
+        // Loads the prescaler
+        tim.trigger_update();
+        tim.reset_counter();
+        TIM::set_cc_value(0, 0x8000);
+        tim.clear_interrupt_flag(Event::all());
+        self.period.store(0, Ordering::SeqCst);
+        tim.listen_interrupt(Event::Update | Event::C1, true);
+
+        // Safety: the interrupt handler only touches the driver state
+        unsafe { NVIC::unmask(TIM::IRQ) };
+        tim.enable_counter();
+    }
+
+    /// Handles the timer interrupt, to be called from the interrupt handler of `TIM`
+    pub fn on_interrupt(&self) {
+        // NOTE(unsafe) the timer is owned by the driver since `start`
+        let mut tim = unsafe { TIM::steal() };
+        interrupt::free(|cs| {
+            let flags = tim.get_interrupt_flag();
+            tim.clear_interrupt_flag(flags);
+
+            // Overflow and half period, both may be pending after a long critical section
+            if flags.contains(Event::Update) {
+                self.next_period(&mut tim, cs);
+            }
+            if flags.contains(Event::C1) {
+                self.next_period(&mut tim, cs);
+            }
+
+            // Alarm, the low 16 bits also match in the periods before the alarm is due
+            if flags.contains(Event::C2) {
+                let mut state = self.state.borrow(cs).borrow_mut();
+                if state.at <= self.now() {
+                    self.trigger_alarm(&mut tim, &mut state);
+                }
+            }
+        });
+    }
+
+    fn next_period(&self, tim: &mut TIM, cs: &interrupt::CriticalSection) {
+        let period = self.period.fetch_add(1, Ordering::Relaxed) + 1;
+        let t = (period as u64) << 15;
+
+        let at = self.state.borrow(cs).borrow().at;
+        if at < t + ALARM_WINDOW {
+            // The alarm is less than one period away, the compare register is now unambiguous
+            tim.listen_interrupt(Event::C2, true);
+        }
+    }
+
+    fn trigger_alarm(&self, tim: &mut TIM, state: &mut AlarmState) {
+        let mut next = state.queue.next_expiration(self.now());
+        while !self.set_alarm(tim, state, next) {
+            next = state.queue.next_expiration(self.now());
+        }
+    }
+
+    /// Returns `false` if `timestamp` has already passed
+    fn set_alarm(&self, tim: &mut TIM, state: &mut AlarmState, timestamp: u64) -> bool {
+        state.at = timestamp;
+
+        let t = self.now();
+        if timestamp <= t {
+            tim.listen_interrupt(Event::C2, false);
+            state.at = u64::MAX;
+            return false;
+        }
+
+        TIM::set_cc_value(1, timestamp as u16 as u32);
+        tim.clear_interrupt_flag(Event::C2);
+
+        // Enable the interrupt only if the alarm is in the current period, `next_period`
+        // takes care of farther alarms
+        tim.listen_interrupt(Event::C2, timestamp - t < ALARM_WINDOW);
+
+        // The counter may have passed the compare value while it was being set
+        if timestamp <= self.now() {
+            tim.listen_interrupt(Event::C2, false);
+            state.at = u64::MAX;
+            return false;
+        }
+        true
+    }
+}
+
+impl<TIM: WithTimeDriver> Default for TimeDriver<TIM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<TIM: WithTimeDriver> Driver for TimeDriver<TIM> {
+    fn now(&self) -> u64 {
+        // NOTE(unsafe) atomic read with no side effects
+        let tim = unsafe { TIM::steal() };
+
+        // The half period counter must be read first, see `next_period`
+        let period = self.period.load(Ordering::Relaxed);
+        compiler_fence(Ordering::Acquire);
+        let counter: u32 = tim.read_count().into();
+
+        // In an odd half period the counter is expected to be >= 0x8000, a smaller value means
+        // the overflow interrupt is pending
+        ((period as u64) << 15) + ((counter ^ ((period & 1) << 15)) as u64)
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        // NOTE(unsafe) the timer is owned by the driver since `start`
+        let mut tim = unsafe { TIM::steal() };
+        interrupt::free(|cs| {
+            let mut state = self.state.borrow(cs).borrow_mut();
+            if state.queue.schedule_wake(at, waker) {
+                self.trigger_alarm(&mut tim, &mut state);
+            }
+        });
+    }
+}
+
+macro_rules! time_driver {
+    ($($TIM:ty: $IRQ:ident,)+) => {
+        $(
+            impl WithTimeDriver for $TIM {
+                const IRQ: pac::Interrupt = pac::Interrupt::$IRQ;
+                unsafe fn steal() -> Self {
+                    <$TIM>::steal()
+                }
+            }
+        )+
+    };
+}
+
+// Advanced timers have separate update and compare interrupts and are not supported
+time_driver! {
+    pac::TIM2: TIM2,
+    pac::TIM3: TIM3,
+}
+
+#[cfg(feature = "medium")]
+time_driver! {
+    pac::TIM4: TIM4,
+}
+
+#[cfg(any(feature = "high", feature = "connectivity"))]
+time_driver! {
+    pac::TIM5: TIM5,
+}
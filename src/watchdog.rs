@@ -2,9 +2,11 @@
 
 use crate::{
     hal_02::watchdog::{Watchdog, WatchdogEnable},
-    pac::{DBGMCU as DBG, IWDG},
-    time::MilliSeconds,
+    pac::{DBGMCU as DBG, IWDG, RCC, WWDG},
+    rcc::{Clocks, Enable},
+    time::{Hertz, MicroSeconds, MilliSeconds},
 };
+use core::convert::Infallible;
 use fugit::ExtU32;
 
 /// Wraps the Independent Watchdog (IWDG) peripheral
@@ -13,12 +15,92 @@ pub struct IndependentWatchdog {
 }
 
 const LSI_KHZ: u32 = 40;
+/// LSI frequency range over voltage and temperature (datasheet)
+const LSI_MIN_HZ: u64 = 30_000;
+const LSI_MAX_HZ: u64 = 60_000;
 const MAX_PR: u8 = 8;
 const MAX_RL: u16 = 0xFFF;
 const KR_ACCESS: u16 = 0x5555;
 const KR_RELOAD: u16 = 0xAAAA;
 const KR_START: u16 = 0xCCCC;
 
+/// Prescaler and reload values of the independent watchdog
+///
+/// The LSI clocking the watchdog is only guaranteed to run between 30 and 60 kHz, so the actual
+/// timeout can be up to 25% shorter or 50% longer than the nominal one at 40 kHz.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IwdgConfig {
+    pr: u8,
+    rl: u16,
+}
+
+impl IwdgConfig {
+    /// Creates a configuration from the prescaler divider bits (0 to 7, divider `4 << pr` up to
+    /// 256) and the reload value (up to `0xFFF`)
+    pub const fn new(pr: u8, rl: u16) -> Self {
+        assert!(pr < MAX_PR && rl <= MAX_RL);
+        Self { pr, rl }
+    }
+
+    /// Finds the configuration with the finest resolution whose timeout is at least `min`
+    /// with the fastest LSI and at most `max` with the slowest one
+    ///
+    /// `max` must be at least twice `min` to cover the LSI tolerance. Returns `None` if no
+    /// configuration fits, the longest timeout is 26.2 s at 40 kHz.
+    pub const fn with_tolerance(min: MilliSeconds, max: MilliSeconds) -> Option<Self> {
+        let min_us = min.ticks() as u64 * 1000;
+        let max_us = max.ticks() as u64 * 1000;
+        let mut pr = 0;
+        // Dividers 7 and 6 are the same
+        while pr < MAX_PR - 1 {
+            let div = 4 << pr;
+            // Counts needed to reach `min` at the highest frequency
+            let mut counts = (min_us * LSI_MAX_HZ + div * 1_000_000 - 1) / (div * 1_000_000);
+            if counts == 0 {
+                counts = 1;
+            }
+            if counts <= MAX_RL as u64 + 1 {
+                let config = Self::new(pr, (counts - 1) as u16);
+                if config.max_timeout().ticks() as u64 <= max_us {
+                    return Some(config);
+                }
+                return None;
+            }
+            pr += 1;
+        }
+        None
+    }
+
+    const fn divider(&self) -> u64 {
+        4 << if self.pr > 6 { 6 } else { self.pr }
+    }
+
+    const fn timeout_at(&self, lsi_hz: u64, round_up: bool) -> MicroSeconds {
+        let cycles = (self.rl as u64 + 1) * self.divider() * 1_000_000;
+        let us = if round_up {
+            (cycles + lsi_hz - 1) / lsi_hz
+        } else {
+            cycles / lsi_hz
+        };
+        MicroSeconds::from_ticks(us as u32)
+    }
+
+    /// Timeout with the nominal LSI frequency of 40 kHz
+    pub const fn timeout(&self) -> MicroSeconds {
+        self.timeout_at(LSI_KHZ as u64 * 1000, false)
+    }
+
+    /// Shortest possible timeout, with the LSI at 60 kHz
+    pub const fn min_timeout(&self) -> MicroSeconds {
+        self.timeout_at(LSI_MAX_HZ, false)
+    }
+
+    /// Longest possible timeout, with the LSI at 30 kHz
+    pub const fn max_timeout(&self) -> MicroSeconds {
+        self.timeout_at(LSI_MIN_HZ, true)
+    }
+}
+
 impl IndependentWatchdog {
     /// Wrap and start the watchdog
     pub fn new(iwdg: IWDG) -> Self {
@@ -94,6 +176,26 @@ impl IndependentWatchdog {
         self.iwdg.kr().write(|w| unsafe { w.key().bits(KR_START) });
     }
 
+    /// Starts the watchdog with explicit prescaler and reload values
+    pub fn start_with(&mut self, config: IwdgConfig) {
+        self.access_registers(|iwdg| {
+            iwdg.pr().modify(|_, w| unsafe { w.pr().bits(config.pr) });
+            iwdg.rlr().modify(|_, w| w.rl().set(config.rl));
+        });
+
+        self.iwdg.kr().write(|w| unsafe { w.key().bits(KR_START) });
+    }
+
+    /// Returns the prescaler and reload values in use
+    pub fn config(&self) -> IwdgConfig {
+        while self.is_pr_updating() || self.iwdg.sr().read().rvu().bit() {}
+
+        IwdgConfig {
+            pr: self.iwdg.pr().read().pr().bits(),
+            rl: self.iwdg.rlr().read().rl().bits(),
+        }
+    }
+
     pub fn feed(&mut self) {
         self.iwdg.kr().write(|w| unsafe { w.key().bits(KR_RELOAD) });
     }
@@ -112,3 +214,202 @@ impl Watchdog for IndependentWatchdog {
         self.feed();
     }
 }
+
+/// Wraps the Window Watchdog (WWDG) peripheral
+///
+/// The 7-bit down-counter is clocked by `PCLK1 / 4096 / 2^WDGTB` and resets the device when it
+/// rolls over from `0x40` to `0x3F`, or when it is reloaded before it got below the window value.
+/// At 36 MHz, timeouts range from 114 μs to 58 ms.
+///
+/// Once started, the watchdog can only be stopped by a reset.
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    pclk1: Hertz,
+    /// Reload value `T[6:0]`
+    counter: u8,
+    /// Window value `W[6:0]`
+    window: u8,
+}
+
+const WWDG_COUNTER_MIN: u8 = 0x40;
+const WWDG_COUNTER_MAX: u8 = 0x7F;
+
+impl WindowWatchdog {
+    /// Wraps the watchdog, which counts from the `PCLK1` clock
+    pub fn new(wwdg: WWDG, clocks: &Clocks) -> Self {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        WWDG::enable(rcc);
+
+        WindowWatchdog {
+            wwdg,
+            pclk1: clocks.pclk1(),
+            counter: WWDG_COUNTER_MAX,
+            window: WWDG_COUNTER_MAX,
+        }
+    }
+
+    /// Debug window watchdog stopped when core is halted
+    pub fn stop_on_debug(&self, dbg: &DBG, stop: bool) {
+        dbg.cr().modify(|_, w| w.dbg_wwdg_stop().bit(stop));
+    }
+
+    /// Counter period in ns for the prescaler `WDGTB` value `tb`
+    fn tick_ns(&self, tb: u8) -> u64 {
+        (4096u64 << tb) * 1_000_000_000 / self.pclk1.raw() as u64
+    }
+
+    fn prescaler(&self) -> u8 {
+        self.wwdg.cfr().read().wdgtb().bits()
+    }
+
+    /// Starts the watchdog with a reset after `timeout` without [`feed`](Self::feed)
+    ///
+    /// Feeding is allowed at any time, unless [`start_windowed`](Self::start_windowed) is used
+    /// instead. The timeout is rounded up to the counter resolution; panics if it is above the
+    /// maximum for the `PCLK1` frequency.
+    pub fn start(&mut self, timeout: MicroSeconds) {
+        self.start_windowed(timeout, 0.micros());
+    }
+
+    /// Starts the watchdog with a reset after `timeout` without [`feed`](Self::feed), or if it
+    /// is fed sooner than `min_feed` after the previous feed
+    pub fn start_windowed(&mut self, timeout: MicroSeconds, min_feed: MicroSeconds) {
+        assert!(min_feed < timeout);
+        let timeout_ns = timeout.ticks() as u64 * 1000;
+
+        // Smallest prescaler for which the timeout fits in the 64 counter steps
+        let tb = (0..4)
+            .find(|&tb| timeout_ns <= 64 * self.tick_ns(tb))
+            .expect("WWDG timeout too long");
+        let tick = self.tick_ns(tb);
+        let steps = ((timeout_ns + tick - 1) / tick).max(1) as u8;
+        let closed = ((min_feed.ticks() as u64 * 1000 + tick / 2) / tick) as u8;
+
+        self.counter = WWDG_COUNTER_MIN - 1 + steps;
+        self.window = self.counter - closed.min(steps - 1);
+
+        self.wwdg.cfr().modify(|_, w| {
+            w.wdgtb().set(tb);
+            w.w().set(self.window)
+        });
+        self.wwdg
+            .cr()
+            .write(|w| w.t().set(self.counter).wdga().enabled());
+    }
+
+    /// Returns the time after which the watchdog resets the device when it is not fed
+    pub fn interval(&self) -> MicroSeconds {
+        let steps = (self.counter - WWDG_COUNTER_MIN + 1) as u64;
+        ((steps * self.tick_ns(self.prescaler()) / 1000) as u32).micros()
+    }
+
+    /// Returns the time after a feed before which feeding again resets the device
+    pub fn closed_window(&self) -> MicroSeconds {
+        let steps = (self.counter - self.window) as u64;
+        ((steps * self.tick_ns(self.prescaler()) / 1000) as u32).micros()
+    }
+
+    /// Returns `true` if the watchdog is started
+    pub fn is_started(&self) -> bool {
+        self.wwdg.cr().read().wdga().is_enabled()
+    }
+
+    /// Reloads the counter, or returns `WouldBlock` if the window is not open yet
+    pub fn try_feed(&mut self) -> nb::Result<(), Infallible> {
+        if self.wwdg.cr().read().t().bits() > self.window {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.wwdg.cr().write(|w| w.t().set(self.counter));
+        Ok(())
+    }
+
+    /// Waits for the window to open and reloads the counter
+    pub fn feed(&mut self) {
+        nb::block!(self.try_feed()).ok();
+    }
+
+    /// Enables the early wakeup interrupt (`WWDG`), raised when the counter reaches `0x40`,
+    /// one counter step before the reset
+    ///
+    /// It can only be disabled by a reset.
+    pub fn listen_early_wakeup(&mut self) {
+        self.wwdg.cfr().modify(|_, w| w.ewi().enable());
+    }
+
+    /// Returns `true` if the early wakeup interrupt is pending
+    pub fn is_early_wakeup(&self) -> bool {
+        self.wwdg.sr().read().ewif().bit_is_set()
+    }
+
+    /// Clears the early wakeup interrupt flag
+    pub fn clear_early_wakeup(&mut self) {
+        self.wwdg.sr().write(|w| w.ewif().finished());
+    }
+}
+
+impl WatchdogEnable for WindowWatchdog {
+    type Time = MicroSeconds;
+
+    fn start<T: Into<Self::Time>>(&mut self, period: T) {
+        self.start(period.into());
+    }
+}
+
+impl Watchdog for WindowWatchdog {
+    fn feed(&mut self) {
+        self.feed();
+    }
+}
+
+#[test]
+fn iwdg_config_tolerance() {
+    // Finest resolution first
+    let config =
+        IwdgConfig::with_tolerance(MilliSeconds::from_ticks(100), MilliSeconds::from_ticks(250))
+            .unwrap();
+    assert_eq!(config, IwdgConfig::new(0, 1499));
+    assert_eq!(config.min_timeout(), MicroSeconds::from_ticks(100_000));
+    assert_eq!(config.timeout(), MicroSeconds::from_ticks(150_000));
+    assert_eq!(config.max_timeout(), MicroSeconds::from_ticks(200_000));
+
+    // The divider is increased until the reload value fits
+    let config = IwdgConfig::with_tolerance(
+        MilliSeconds::from_ticks(1000),
+        MilliSeconds::from_ticks(2000),
+    )
+    .unwrap();
+    assert_eq!(config, IwdgConfig::new(2, 3749));
+    assert!(config.min_timeout() >= MicroSeconds::from_ticks(1_000_000));
+    assert!(config.max_timeout() <= MicroSeconds::from_ticks(2_000_000));
+
+    // Longest timeout
+    let config = IwdgConfig::with_tolerance(
+        MilliSeconds::from_ticks(17_476),
+        MilliSeconds::from_ticks(35_000),
+    )
+    .unwrap();
+    assert_eq!(config, IwdgConfig::new(6, 4095));
+
+    // Too long, or window narrower than the LSI tolerance
+    assert_eq!(
+        IwdgConfig::with_tolerance(
+            MilliSeconds::from_ticks(17_500),
+            MilliSeconds::from_ticks(40_000)
+        ),
+        None
+    );
+    assert_eq!(
+        IwdgConfig::with_tolerance(MilliSeconds::from_ticks(100), MilliSeconds::from_ticks(150)),
+        None
+    );
+}
+
+#[test]
+fn iwdg_config_short() {
+    let config =
+        IwdgConfig::with_tolerance(MilliSeconds::from_ticks(0), MilliSeconds::from_ticks(1))
+            .unwrap();
+    assert_eq!(config, IwdgConfig::new(0, 0));
+    assert_eq!(config.timeout(), MicroSeconds::from_ticks(100));
+    assert_eq!(config.max_timeout(), MicroSeconds::from_ticks(134));
+}